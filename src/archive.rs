@@ -0,0 +1,173 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+use zstd::Decoder as ZstdDecoder;
+
+/// Contents of the files found in tar archives, keyed by the path of the archive and of the file in
+/// it. A tar stream can only be read from the start, so the files are read while the archive is
+/// listed rather than decompressing the whole archive again for each one of them
+static TAR_FILES: Mutex<BTreeMap<(PathBuf, PathBuf), Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+/// Kinds of archives and compressed files that logs can be read from without extracting them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+    /// A single gzip compressed file
+    Gz,
+    /// A single zstd compressed file
+    Zst,
+}
+
+impl ArchiveKind {
+    /// Work out the kind of archive from the file name at 'path', if it is one
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        [
+            (".tar.gz", Self::TarGz),
+            (".tgz", Self::TarGz),
+            (".tar.zst", Self::TarZst),
+            (".tar", Self::Tar),
+            (".zip", Self::Zip),
+            (".gz", Self::Gz),
+            (".zst", Self::Zst),
+        ]
+        .into_iter()
+        .find(|(suffix, _)| name.ends_with(suffix))
+        .map(|(_, kind)| kind)
+    }
+
+    /// Open the archive at 'path' and undo any compression around the tar stream
+    fn open_tar(self, path: &Path) -> io::Result<tar::Archive<Box<dyn Read>>> {
+        let file = BufReader::new(File::open(path)?);
+        let reader: Box<dyn Read> = match self {
+            Self::TarGz => Box::new(GzDecoder::new(file)),
+            Self::TarZst => Box::new(ZstdDecoder::with_buffer(file)?),
+            _ => Box::new(file),
+        };
+        Ok(tar::Archive::new(reader))
+    }
+
+    /// Open a single compressed file
+    fn open_compressed(self, path: &Path) -> io::Result<Box<dyn Read>> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(match self {
+            Self::Zst => Box::new(ZstdDecoder::with_buffer(file)?),
+            _ => Box::new(GzDecoder::new(file)),
+        })
+    }
+}
+
+/// List the paths of the files stored in the archive at 'path' that 'wanted' accepts. For a single
+/// compressed file this is the name of the file with the compression extension removed. The
+/// files in tar archives are read as they're listed so that `read_member` doesnt have to
+/// decompress the archive again
+pub fn list_members(
+    path: &Path,
+    kind: ArchiveKind,
+    wanted: impl Fn(&Path) -> bool,
+) -> Result<Vec<PathBuf>> {
+    match kind {
+        ArchiveKind::Zip => {
+            let archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+            Ok(archive
+                .file_names()
+                .filter(|name| !name.ends_with('/'))
+                .map(PathBuf::from)
+                .filter(|member| wanted(member))
+                .collect())
+        }
+        ArchiveKind::Tar | ArchiveKind::TarGz | ArchiveKind::TarZst => {
+            let mut members = Vec::new();
+            for mut entry in kind.open_tar(path)?.entries()?.flatten() {
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let Ok(member) = entry.path().map(|member| member.to_path_buf()) else {
+                    continue;
+                };
+                if !wanted(&member) {
+                    continue;
+                }
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes)?;
+                TAR_FILES
+                    .lock()
+                    .unwrap()
+                    .insert((path.to_path_buf(), member.clone()), bytes);
+                members.push(member);
+            }
+            Ok(members)
+        }
+        ArchiveKind::Gz | ArchiveKind::Zst => Ok(path
+            .file_stem()
+            .map(PathBuf::from)
+            .filter(|member| wanted(member))
+            .into_iter()
+            .collect()),
+    }
+}
+
+/// Read the file at 'member' out of the archive at 'path'. If 'limit' is set then at most that
+/// many bytes are read. Files from tar archives are taken from what was read while listing them
+pub fn read_member(
+    path: &Path,
+    kind: ArchiveKind,
+    member: &Path,
+    limit: Option<u64>,
+) -> Result<Vec<u8>> {
+    if let Some(cached) = read_cached(path, member, limit) {
+        return Ok(cached);
+    }
+    let limit = limit.unwrap_or(u64::MAX);
+    let mut bytes = Vec::new();
+    match kind {
+        ArchiveKind::Zip => {
+            let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+            let name = member.to_string_lossy();
+            archive
+                .by_name(&name)?
+                .take(limit)
+                .read_to_end(&mut bytes)?;
+        }
+        ArchiveKind::Tar | ArchiveKind::TarGz | ArchiveKind::TarZst => {
+            let mut archive = kind.open_tar(path)?;
+            let mut entry = archive
+                .entries()?
+                .flatten()
+                .find(|entry| entry.path().is_ok_and(|path| path == member))
+                .ok_or_else(|| anyhow!("{} not found in archive", member.display()))?;
+            (&mut entry).take(limit).read_to_end(&mut bytes)?;
+        }
+        ArchiveKind::Gz | ArchiveKind::Zst => {
+            kind.open_compressed(path)?
+                .take(limit)
+                .read_to_end(&mut bytes)?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Take the contents of 'member' if it was read while the tar archive at 'path' was listed. Reading
+/// only part of it leaves it cached, since the whole file is read once its format is known
+fn read_cached(path: &Path, member: &Path, limit: Option<u64>) -> Option<Vec<u8>> {
+    let mut cache = TAR_FILES.lock().unwrap();
+    let key = (path.to_path_buf(), member.to_path_buf());
+    match limit {
+        Some(limit) => cache.get(&key).map(|bytes| {
+            let len = usize::try_from(limit).map_or(bytes.len(), |limit| limit.min(bytes.len()));
+            bytes[..len].to_vec()
+        }),
+        None => cache.remove(&key),
+    }
+}
@@ -0,0 +1,250 @@
+use std::{borrow::Cow, fmt::Display};
+
+/// How many bytes from the start of a file are looked at when guessing its encoding
+const SAMPLE_SIZE: usize = 4096;
+
+/// Text encodings that log files are decoded from. Decoding is always lossy, invalid sequences are
+/// replaced rather than causing the file to be rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    /// Guess the encoding of a file from the start of its contents
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            Self::Utf8Bom
+        } else if bytes.starts_with(&[0xFF, 0xFE]) {
+            Self::Utf16Le
+        } else if bytes.starts_with(&[0xFE, 0xFF]) {
+            Self::Utf16Be
+        } else if let Some(encoding) = Self::detect_utf16(&bytes[..bytes.len().min(SAMPLE_SIZE)]) {
+            encoding
+        } else if Self::is_mostly_utf8(bytes) {
+            Self::Utf8
+        } else {
+            Self::Latin1
+        }
+    }
+
+    /// UTF-16 without a BOM is recognised by mostly ASCII text leaving every other byte zero
+    fn detect_utf16(sample: &[u8]) -> Option<Self> {
+        if sample.len() < 4 {
+            return None;
+        }
+        let (even_zeros, odd_zeros) = sample
+            .iter()
+            .enumerate()
+            .filter(|(_, byte)| **byte == 0)
+            .fold((0, 0), |(even, odd), (i, _)| {
+                if i % 2 == 0 {
+                    (even + 1, odd)
+                } else {
+                    (even, odd + 1)
+                }
+            });
+        let half = sample.len() / 2;
+        if odd_zeros > half / 2 && even_zeros < half / 10 {
+            Some(Self::Utf16Le)
+        } else if even_zeros > half / 2 && odd_zeros < half / 10 {
+            Some(Self::Utf16Be)
+        } else {
+            None
+        }
+    }
+
+    /// A file with a few stray bytes is still treated as UTF-8 as long as it has at least as many
+    /// valid multi byte characters as invalid bytes, otherwise it's most likely Latin-1
+    fn is_mostly_utf8(bytes: &[u8]) -> bool {
+        let (multi_byte, invalid) = bytes.utf8_chunks().fold((0, 0), |(multi, invalid), chunk| {
+            (
+                multi + chunk.valid().chars().filter(|c| c.len_utf8() > 1).count(),
+                invalid + chunk.invalid().len(),
+            )
+        });
+        invalid == 0 || multi_byte >= invalid
+    }
+
+    /// Decode 'bytes' into text, skipping the byte order mark if there is one. Valid UTF-8 is
+    /// borrowed rather than copied
+    pub fn decode(self, bytes: &[u8]) -> Cow<'_, str> {
+        match self {
+            Self::Utf8 | Self::Utf8Bom => {
+                String::from_utf8_lossy(bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes))
+            }
+            Self::Utf16Le => Cow::Owned(decode_utf16(
+                bytes.strip_prefix(&[0xFF, 0xFE]).unwrap_or(bytes),
+                u16::from_le_bytes,
+            )),
+            Self::Utf16Be => Cow::Owned(decode_utf16(
+                bytes.strip_prefix(&[0xFE, 0xFF]).unwrap_or(bytes),
+                u16::from_be_bytes,
+            )),
+            Self::Latin1 => bytes.iter().copied().map(char::from).collect(),
+        }
+    }
+
+    /// Split 'bytes' into lines, without their line endings, along with the offset of each line
+    /// in 'bytes'. This matches how `str::lines` splits the decoded text
+    pub fn split_lines(self, bytes: &[u8]) -> Vec<(usize, &[u8])> {
+        let (newline, carriage_return): ([u8; 2], &[u8]) = match self {
+            Self::Utf8 | Self::Utf8Bom | Self::Latin1 => {
+                let mut offset = 0;
+                return bytes
+                    .split(|byte| *byte == b'\n')
+                    .map(|line| {
+                        let start = offset;
+                        offset += line.len() + 1;
+                        (start, line.strip_suffix(b"\r").unwrap_or(line))
+                    })
+                    // Text ending with a newline doesnt have an empty line after it
+                    .filter(|(start, _)| *start < bytes.len())
+                    .collect();
+            }
+            Self::Utf16Le => ([b'\n', 0], &[b'\r', 0]),
+            Self::Utf16Be => ([0, b'\n'], &[0, b'\r']),
+        };
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (i, unit) in bytes.chunks_exact(2).enumerate() {
+            if unit == newline {
+                lines.push((start, &bytes[start..i * 2]));
+                start = i * 2 + 2;
+            }
+        }
+        if start < bytes.len() {
+            lines.push((start, &bytes[start..]));
+        }
+        lines
+            .into_iter()
+            .map(|(offset, line)| (offset, line.strip_suffix(carriage_return).unwrap_or(line)))
+            .collect()
+    }
+
+    /// Returns the length of the part of 'bytes' that ends with a complete line, so that a
+    /// partially written line can be left until the rest of it arrives
+    pub fn complete_lines_len(self, bytes: &[u8]) -> usize {
+        match self {
+            Self::Utf8 | Self::Utf8Bom | Self::Latin1 => bytes
+                .iter()
+                .rposition(|byte| *byte == b'\n')
+                .map_or(0, |i| i + 1),
+            Self::Utf16Le | Self::Utf16Be => {
+                let newline = if self == Self::Utf16Le {
+                    [b'\n', 0]
+                } else {
+                    [0, b'\n']
+                };
+                bytes
+                    .chunks_exact(2)
+                    .rposition(|unit| unit == newline)
+                    .map_or(0, |i| (i + 1) * 2)
+            }
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Utf8 => "utf-8",
+            Self::Utf8Bom => "utf-8 bom",
+            Self::Utf16Le => "utf-16le",
+            Self::Utf16Be => "utf-16be",
+            Self::Latin1 => "latin-1",
+        })
+    }
+}
+
+fn decode_utf16(bytes: &[u8], to_unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|unit| to_unit([unit[0], unit[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn detects_byte_order_marks() {
+        assert_eq!(Encoding::detect(b"\xEF\xBB\xBFline\n"), Encoding::Utf8Bom);
+        assert_eq!(Encoding::detect(b"\xFF\xFEl\0"), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(b"\xFE\xFF\0l"), Encoding::Utf16Be);
+    }
+
+    #[test]
+    fn detects_utf16_without_bom() {
+        assert_eq!(
+            Encoding::detect(&utf16le("first line\nsecond line\n")),
+            Encoding::Utf16Le
+        );
+        assert_eq!(
+            Encoding::detect(&utf16be("first line\nsecond line\n")),
+            Encoding::Utf16Be
+        );
+    }
+
+    #[test]
+    fn detects_utf8_and_latin1() {
+        assert_eq!(Encoding::detect(b"plain ascii\n"), Encoding::Utf8);
+        assert_eq!(
+            Encoding::detect("caf\u{e9} device\n".as_bytes()),
+            Encoding::Utf8
+        );
+        assert_eq!(Encoding::detect(b"caf\xE9 device\n"), Encoding::Latin1);
+        assert_eq!(Encoding::Latin1.decode(b"caf\xE9"), "caf\u{e9}");
+    }
+
+    #[test]
+    fn splits_utf8_lines_with_offsets() {
+        assert_eq!(
+            Encoding::Utf8.split_lines(b"one\r\ntwo\n\nthree"),
+            vec![(0, &b"one"[..]), (5, b"two"), (9, b""), (10, b"three")]
+        );
+        assert_eq!(
+            Encoding::Utf8.split_lines(b"one\ntwo\n"),
+            vec![(0, &b"one"[..]), (4, b"two")]
+        );
+        assert!(Encoding::Utf8.split_lines(b"").is_empty());
+    }
+
+    #[test]
+    fn splits_utf16_lines_with_offsets() {
+        let bytes = utf16le("ab\r\ncd\nef");
+        let lines = Encoding::Utf16Le.split_lines(&bytes);
+        let offsets = lines.iter().map(|(offset, _)| *offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 8, 14]);
+        let text = lines
+            .iter()
+            .map(|(_, line)| Encoding::Utf16Le.decode(line).into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(text, vec!["ab", "cd", "ef"]);
+    }
+
+    #[test]
+    fn complete_lines_leave_out_a_partial_line() {
+        assert_eq!(Encoding::Utf8.complete_lines_len(b"one\ntwo"), 4);
+        assert_eq!(Encoding::Utf8.complete_lines_len(b"one\ntwo\n"), 8);
+        assert_eq!(Encoding::Utf8.complete_lines_len(b"partial"), 0);
+        assert_eq!(
+            Encoding::Utf16Be.complete_lines_len(&utf16be("one\ntwo")),
+            8
+        );
+    }
+}
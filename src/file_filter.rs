@@ -0,0 +1,158 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Decides which files found while searching the given paths are opened as logs
+#[derive(Clone, Debug)]
+pub struct FileFilter {
+    extensions: Vec<String>,
+    include: GlobSet,
+    exclude: GlobSet,
+    max_depth: Option<usize>,
+}
+
+impl FileFilter {
+    /// Build a filter from the command line options. Include patterns that start with '!' exclude
+    /// files instead. Without any extensions or include patterns only .txt files are opened, and
+    /// with include patterns but no extensions the extension isnt checked at all
+    pub fn new(
+        extensions: &[String],
+        include: &[String],
+        exclude: &[String],
+        max_depth: Option<usize>,
+    ) -> Result<Self> {
+        let (negated, include): (Vec<_>, Vec<_>) =
+            include.iter().partition(|pattern| pattern.starts_with('!'));
+        let mut extensions = extensions
+            .iter()
+            .map(|extension| extension.trim_start_matches('.').to_string())
+            .collect::<Vec<_>>();
+        if extensions.is_empty() && include.is_empty() {
+            extensions.push("txt".to_string());
+        }
+        Ok(Self {
+            extensions,
+            include: build_globs(include.into_iter().map(String::as_str))?,
+            exclude: build_globs(
+                negated
+                    .into_iter()
+                    .map(|pattern| &pattern[1..])
+                    .chain(exclude.iter().map(String::as_str)),
+            )?,
+            max_depth,
+        })
+    }
+
+    /// Returns true if the file at 'path' should be opened
+    pub fn matches(&self, path: &Path) -> bool {
+        let extension_matches = self.extensions.is_empty()
+            || path.extension().is_some_and(|ext| {
+                self.extensions
+                    .iter()
+                    .any(|extension| ext.eq_ignore_ascii_case(extension))
+            });
+        extension_matches
+            && (self.include.is_empty() || glob_matches(&self.include, path))
+            && !self.excludes(path)
+    }
+
+    /// Returns true if 'path' matches one of the exclude patterns. Directories that are excluded
+    /// arent searched at all
+    pub fn excludes(&self, path: &Path) -> bool {
+        glob_matches(&self.exclude, path)
+    }
+
+    /// Returns true if a directory 'depth' levels below one of the given paths should be searched
+    pub fn within_depth(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+}
+
+/// Patterns are matched against the file name as well as the whole path, so that 'vr*.txt' and
+/// '*/drivers/*' both work as expected
+fn glob_matches(globs: &GlobSet, path: &Path) -> bool {
+    path.file_name().is_some_and(|name| globs.is_match(name)) || globs.is_match(path)
+}
+
+fn build_globs<'a>(patterns: impl Iterator<Item = &'a str>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(
+            GlobBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .with_context(|| format!("Invalid glob pattern '{pattern}'"))?,
+        );
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(ToString::to_string).collect()
+    }
+
+    fn filter(extensions: &[&str], include: &[&str], exclude: &[&str]) -> FileFilter {
+        FileFilter::new(
+            &strings(extensions),
+            &strings(include),
+            &strings(exclude),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn only_txt_files_by_default() {
+        let filter = filter(&[], &[], &[]);
+        assert!(filter.matches(Path::new("logs/vrserver.txt")));
+        assert!(filter.matches(Path::new("logs/VRSERVER.TXT")));
+        assert!(!filter.matches(Path::new("logs/server.log")));
+    }
+
+    #[test]
+    fn extensions_replace_the_default() {
+        let filter = filter(&["log", ".txt"], &[], &[]);
+        assert!(filter.matches(Path::new("logs/server.log")));
+        assert!(filter.matches(Path::new("logs/vrserver.txt")));
+        assert!(!filter.matches(Path::new("logs/dump.mdmp")));
+    }
+
+    #[test]
+    fn include_patterns_without_extensions_match_any_extension() {
+        let filter = filter(&[], &["vr*"], &[]);
+        assert!(filter.matches(Path::new("logs/vrserver.txt")));
+        assert!(filter.matches(Path::new("logs/vrclient.log")));
+        assert!(!filter.matches(Path::new("logs/content_log.txt")));
+    }
+
+    #[test]
+    fn excludes_match_names_and_whole_paths() {
+        let filter = filter(&[], &["!*.previous.txt"], &["*/drivers/*"]);
+        assert!(filter.matches(Path::new("logs/vrserver.txt")));
+        assert!(!filter.matches(Path::new("logs/vrserver.previous.txt")));
+        assert!(!filter.matches(Path::new("logs/drivers/lighthouse.txt")));
+        assert!(filter.excludes(Path::new("logs/drivers/lighthouse.txt")));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let filter = FileFilter::new(&[], &[], &[], Some(1)).unwrap();
+        assert!(filter.within_depth(0));
+        assert!(filter.within_depth(1));
+        assert!(!filter.within_depth(2));
+        assert!(FileFilter::new(&[], &[], &[], None)
+            .unwrap()
+            .within_depth(100));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        let err = FileFilter::new(&[], &strings(&["[vr"]), &[], None).unwrap_err();
+        assert!(err.to_string().contains("'[vr'"));
+    }
+}
@@ -0,0 +1,171 @@
+use std::{fs, path::Path, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::NaiveDateTime;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{parser::get_level, types::Entry};
+
+use super::{level_from_text, LogFormat};
+
+/// Layout of the formats config file, a list of `[[format]]` tables
+#[derive(Debug, Deserialize)]
+struct FormatsFile {
+    #[serde(default)]
+    format: Vec<FormatConfig>,
+}
+
+/// A single user defined format as written in the config file
+#[derive(Debug, Deserialize)]
+struct FormatConfig {
+    name: String,
+    /// Regex matching the first line of an entry. Must have `timestamp` and `message` named groups
+    /// and may have a `level` group
+    header: String,
+    /// chrono format string used to parse the `timestamp` group
+    timestamp_format: String,
+    /// Level used when the header has no `level` group, otherwise one is picked from the message
+    default_level: Option<String>,
+    /// Only lines matching this regex are appended to the previous entry, the rest are skipped and
+    /// recorded as problems with the log. If it's not set every line that is not a header is
+    /// appended
+    continuation: Option<String>,
+    /// Entries whose message matches this regex start a new session
+    session: Option<String>,
+}
+
+/// A log format loaded from the config file at startup
+#[derive(Debug)]
+pub struct CustomFormat {
+    name: String,
+    header: Regex,
+    timestamp_format: String,
+    default_level: Option<Arc<str>>,
+    continuation: Option<Regex>,
+    session: Option<Regex>,
+}
+
+impl CustomFormat {
+    fn new(config: FormatConfig) -> Result<Self> {
+        let header = Regex::new(&config.header)
+            .with_context(|| format!("Invalid header regex for format '{}'", config.name))?;
+        for group in ["timestamp", "message"] {
+            if !header.capture_names().flatten().any(|name| name == group) {
+                bail!(
+                    "Header regex for format '{}' is missing the '{group}' group",
+                    config.name
+                );
+            }
+        }
+        let continuation = config
+            .continuation
+            .map(|continuation| Regex::new(&continuation))
+            .transpose()
+            .with_context(|| format!("Invalid continuation regex for format '{}'", config.name))?;
+        let session = config
+            .session
+            .map(|session| Regex::new(&session))
+            .transpose()
+            .with_context(|| format!("Invalid session regex for format '{}'", config.name))?;
+
+        Ok(Self {
+            name: config.name,
+            header,
+            timestamp_format: config.timestamp_format,
+            default_level: config.default_level.as_deref().map(get_level),
+            continuation,
+            session,
+        })
+    }
+}
+
+impl LogFormat for CustomFormat {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(&self, line: &str) -> bool {
+        self.header.is_match(line)
+    }
+
+    fn parse_header(&self, line: &str) -> Result<Entry> {
+        let captures = self
+            .header
+            .captures(line)
+            .ok_or_else(|| anyhow!("Line is not a '{}' log header", self.name))?;
+        // Both of these groups are checked for when the format is loaded, they can only be missing
+        // here if they're in an optional part of the regex
+        let timestamp = captures.name("timestamp").map_or("", |m| m.as_str());
+        let log_text = captures.name("message").map_or("", |m| m.as_str());
+        let timestamp = NaiveDateTime::parse_from_str(timestamp, &self.timestamp_format)?;
+        let level = match (captures.name("level"), &self.default_level) {
+            (Some(level), _) => get_level(level.as_str()),
+            (None, Some(level)) => level.clone(),
+            (None, None) => level_from_text(log_text),
+        };
+
+        Ok(Entry::new(timestamp, level, log_text))
+    }
+
+    fn is_continuation(&self, line: &str) -> bool {
+        self.continuation
+            .as_ref()
+            .is_none_or(|continuation| continuation.is_match(line))
+    }
+
+    fn session_banner(&self, entry: &Entry) -> Option<String> {
+        self.session
+            .as_ref()
+            .filter(|session| session.is_match(&entry.log_data()))
+            .map(|_| entry.log_data().to_string())
+    }
+}
+
+/// Read the user defined formats from the TOML file at 'path'
+pub fn load_formats(path: &Path) -> Result<Vec<CustomFormat>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read formats file {}", path.display()))?;
+    let file: FormatsFile = toml::from_str(&text)
+        .with_context(|| format!("Failed to parse formats file {}", path.display()))?;
+    file.format.into_iter().map(CustomFormat::new).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_line, types::Log};
+
+    #[test]
+    fn lines_that_arent_continuations_are_skipped_and_recorded() {
+        let format = CustomFormat::new(FormatConfig {
+            name: "driver".to_string(),
+            header: r"^\[(?P<timestamp>[^\]]+)\] (?P<message>.*)$".to_string(),
+            timestamp_format: "%Y-%m-%d %H:%M:%S".to_string(),
+            default_level: None,
+            continuation: Some(r"^\s".to_string()),
+            session: None,
+        })
+        .unwrap();
+        let mut log = Log::new(&"driver", Arc::new(format));
+        let lines = [
+            "[2024-07-12 10:15:00] started",
+            "  detail",
+            "not part of it",
+            "[2024-07-12 10:15:01] next",
+        ];
+        for (offset, line) in lines.iter().enumerate() {
+            parse_line(&mut log, line, offset as u64);
+        }
+
+        let texts = log
+            .entries()
+            .iter()
+            .map(|entry| entry.log_data().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["started\n  detail", "next"]);
+        let diagnostics = format!("{:?}", log.diagnostics());
+        assert_eq!(log.diagnostics().len(), 1);
+        assert!(diagnostics.contains("line: 3, text: \"not part of it\""));
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use regex::Regex;
+
+use crate::{parser::get_level, types::Entry};
+
+mod custom;
+mod source;
+mod steam;
+mod steamvr;
+mod text;
+
+use source::Source;
+use steam::Steam;
+use steamvr::SteamVr;
+
+pub use source::Console;
+pub use text::PlainText;
+
+/// Formats loaded from the user's config file at startup
+static CUSTOM: Mutex<Vec<Arc<dyn LogFormat>>> = Mutex::new(Vec::new());
+
+/// Describes one log layout so that the ingest code can turn raw lines into `Entry` values
+/// without knowing anything about the format itself
+pub trait LogFormat: Debug + Send + Sync {
+    /// Short name used to refer to the format on the command line and in the UI
+    fn name(&self) -> &str;
+
+    /// Returns true if 'line' is the first line of a new entry in this format
+    fn detect(&self, line: &str) -> bool;
+
+    /// Parse a line that `detect` accepted into a new `Entry`
+    fn parse_header(&self, line: &str) -> Result<Entry>;
+
+    /// Returns true if a line that is not a header should be appended to the previous entry
+    fn is_continuation(&self, _line: &str) -> bool {
+        true
+    }
+
+    /// If 'entry' is the banner written when the program starts up, return a label for the
+    /// session it begins
+    fn session_banner(&self, _entry: &Entry) -> Option<String> {
+        None
+    }
+
+    /// A copy of the format of its own for reading a file last modified at 'modified', for formats
+    /// that stamp entries with that time or carry state from one line to the next. `None` if the
+    /// same format can be used for every file
+    fn for_file(&self, _modified: NaiveDateTime) -> Option<Arc<dyn LogFormat>> {
+        None
+    }
+}
+
+/// Return a Vec of all known log formats, in the order they should be tried. User defined formats
+/// come first so that they win over a built in format that matches equally well
+pub fn formats() -> Vec<Arc<dyn LogFormat>> {
+    let mut formats = CUSTOM.lock().unwrap().clone();
+    formats.extend([
+        Arc::new(SteamVr) as Arc<dyn LogFormat>,
+        Arc::new(Steam),
+        Arc::new(Source),
+    ]);
+    formats
+}
+
+/// Every format that can be picked by name on the command line. The console and plain text
+/// formats match any line, so they arent in `formats` and are never detected from the contents of
+/// a file
+pub fn named_formats() -> Vec<Arc<dyn LogFormat>> {
+    let mut formats = formats();
+    formats.extend([
+        Arc::new(Console::new(NaiveDateTime::default())) as Arc<dyn LogFormat>,
+        Arc::new(PlainText::new(NaiveDateTime::default())),
+    ]);
+    formats
+}
+
+/// Load the user defined formats from the config file at 'path' and make them available to the
+/// parser alongside the built in ones
+pub fn register_formats(path: &Path) -> Result<()> {
+    let loaded = custom::load_formats(path)?;
+    CUSTOM.lock().unwrap().extend(
+        loaded
+            .into_iter()
+            .map(|format| Arc::new(format) as Arc<dyn LogFormat>),
+    );
+    Ok(())
+}
+
+/// Where the formats config file is looked for if one isnt given on the command line
+pub fn default_formats_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("valve_log_viewer").join("formats.toml"))
+}
+
+/// Look up a format by name, ignoring case
+pub fn find_format(name: &str) -> Option<Arc<dyn LogFormat>> {
+    named_formats()
+        .into_iter()
+        .find(|format| format.name().eq_ignore_ascii_case(name))
+}
+
+/// Pick the format that recognises the most header lines in 'lines'. Returns `None` if no format
+/// recognises any of them
+pub fn detect_format(lines: &[String]) -> Option<Arc<dyn LogFormat>> {
+    formats()
+        .into_iter()
+        .map(|format| {
+            let score = lines.iter().filter(|line| format.detect(line)).count();
+            (score, format)
+        })
+        .filter(|(score, _)| *score > 0)
+        // max_by_key returns the last of equal elements, reversing means ties go to whichever
+        // format is listed first
+        .rev()
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, format)| format)
+}
+
+/// For formats that dont write a level we pick one based on the words used in the message so that
+/// the level filter still has something useful to work with
+fn level_from_text(text: &str) -> Arc<str> {
+    static ERROR_RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?i)\b(error|failed|failure|fatal)\b").unwrap());
+    static WARNING_RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?i)\bwarn(ing)?\b").unwrap());
+    if ERROR_RE.is_match(text) {
+        get_level("Error")
+    } else if WARNING_RE.is_match(text) {
+        get_level("Warning")
+    } else {
+        get_level("Info")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_format_shown_in_the_files_list_can_be_picked_by_name() {
+        for name in ["steamvr", "steam", "source", "Console", "TEXT"] {
+            assert!(find_format(name).is_some(), "{name}");
+        }
+        assert!(find_format("auto").is_none());
+    }
+
+    #[test]
+    fn formats_picked_by_name_get_a_copy_for_each_file() {
+        let modified =
+            NaiveDateTime::parse_from_str("2024-07-12 10:15:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        for name in ["console", "text"] {
+            let format = find_format(name).unwrap().for_file(modified).unwrap();
+            assert_eq!(format.name(), name);
+            let entry = format.parse_header("no timestamp here").unwrap();
+            assert_eq!(*entry.timestamp(), modified);
+        }
+        assert!(find_format("steamvr").unwrap().for_file(modified).is_none());
+    }
+}
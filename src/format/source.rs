@@ -0,0 +1,94 @@
+use std::sync::{Arc, LazyLock, Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use regex::Regex;
+
+use crate::types::Entry;
+
+use super::{level_from_text, LogFormat};
+
+/// Matches both the `L MM/DD/YYYY - HH:MM:SS: text` lines from server logs and the
+/// `MM/DD/YYYY - HH:MM:SS: text` lines written to console.log when `con_timestamp` is enabled
+static RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:L )?(\d{2}/\d{2}/\d{4} - \d{2}:\d{2}:\d{2}): ?(.*)$").unwrap()
+});
+
+/// The format used by Source and Source 2 dedicated servers for the server logs in the `logs`
+/// folder. A console.log written with `con_timestamp` enabled is recognised as this format too
+#[derive(Debug)]
+pub struct Source;
+
+impl LogFormat for Source {
+    fn name(&self) -> &'static str {
+        "source"
+    }
+
+    fn detect(&self, line: &str) -> bool {
+        RE.is_match(line)
+    }
+
+    fn parse_header(&self, line: &str) -> Result<Entry> {
+        let (timestamp, log_text) = RE
+            .captures(line)
+            .ok_or_else(|| anyhow!("Line is not a Source log header"))?
+            .extract()
+            .1
+            .into();
+        let timestamp = NaiveDateTime::parse_from_str(timestamp, "%m/%d/%Y - %H:%M:%S")?;
+
+        Ok(Entry::new(timestamp, level_from_text(log_text), log_text))
+    }
+
+    /// Servers start a new log file whenever the map changes, each one begins with this line
+    fn session_banner(&self, entry: &Entry) -> Option<String> {
+        entry
+            .log_data()
+            .starts_with("Log file started")
+            .then(|| entry.log_data().to_string())
+    }
+}
+
+/// The console.log written with `-condebug`. Lines only start with a timestamp when `con_timestamp`
+/// is enabled, so every line is its own entry and lines without one take the time of the line
+/// before them, or the time the file was last modified if there is none yet
+#[derive(Debug)]
+pub struct Console {
+    timestamp: Mutex<NaiveDateTime>,
+}
+
+impl Console {
+    pub const fn new(timestamp: NaiveDateTime) -> Self {
+        Self {
+            timestamp: Mutex::new(timestamp),
+        }
+    }
+}
+
+impl LogFormat for Console {
+    fn name(&self) -> &'static str {
+        "console"
+    }
+
+    fn detect(&self, _line: &str) -> bool {
+        true
+    }
+
+    fn parse_header(&self, line: &str) -> Result<Entry> {
+        let mut timestamp = self.timestamp.lock().unwrap();
+        let log_text = match RE.captures(line) {
+            Some(captures) => {
+                let (_, [time, log_text]) = captures.extract();
+                *timestamp = NaiveDateTime::parse_from_str(time, "%m/%d/%Y - %H:%M:%S")?;
+                log_text
+            }
+            None => line,
+        };
+
+        Ok(Entry::new(*timestamp, level_from_text(log_text), log_text))
+    }
+
+    fn for_file(&self, modified: NaiveDateTime) -> Option<Arc<dyn LogFormat>> {
+        Some(Arc::new(Self::new(modified)))
+    }
+}
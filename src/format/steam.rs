@@ -0,0 +1,41 @@
+use std::sync::LazyLock;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use regex::Regex;
+
+use crate::types::Entry;
+
+use super::{level_from_text, LogFormat};
+
+/// Matches the `[YYYY-MM-DD HH:MM:SS] text` lines written by the Steam client. Any `[tag]` that
+/// follows the timestamp is left as part of the text
+static RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2})\] ?(.*)$").unwrap());
+
+/// The format used by the Steam client logs such as `content_log.txt`, `connection_log.txt` and
+/// `stderr.txt`. These logs dont have a level so one is picked from the message text
+#[derive(Debug)]
+pub struct Steam;
+
+impl LogFormat for Steam {
+    fn name(&self) -> &'static str {
+        "steam"
+    }
+
+    fn detect(&self, line: &str) -> bool {
+        RE.is_match(line)
+    }
+
+    fn parse_header(&self, line: &str) -> Result<Entry> {
+        let (timestamp, log_text) = RE
+            .captures(line)
+            .ok_or_else(|| anyhow!("Line is not a Steam client log header"))?
+            .extract()
+            .1
+            .into();
+        let timestamp = NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")?;
+
+        Ok(Entry::new(timestamp, level_from_text(log_text), log_text))
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::LazyLock;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use regex::Regex;
+
+use crate::{parser::get_level, types::Entry};
+
+use super::LogFormat;
+
+/// Matches the `Www Mmm DD YYYY HH:MM:SS.fff [Level] - text` lines written by `SteamVR`. The day of
+/// the week is left out of the timestamp capture because chrono doesnt need it
+static RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\w{3} (\w{3} \d{2} \d{4} \d{2}:\d{2}:\d{2}\.\d+) \[(\w+?)\] - (.+?)$").unwrap()
+});
+
+/// Matches the banner each `SteamVR` process writes when it starts, such as
+/// `==== Starting vrserver version 2.6.2 (...) ====`
+static BANNER_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)^[=\s]*(starting (?:vr\w+|steamvr)\b.*?|log file (?:started|opened)\b.*?)[=\s]*$",
+    )
+    .unwrap()
+});
+
+/// The format used by vrserver, vrcompositor, vrmonitor and the other `SteamVR` processes
+#[derive(Debug)]
+pub struct SteamVr;
+
+impl LogFormat for SteamVr {
+    fn name(&self) -> &'static str {
+        "steamvr"
+    }
+
+    fn detect(&self, line: &str) -> bool {
+        RE.is_match(line)
+    }
+
+    fn parse_header(&self, line: &str) -> Result<Entry> {
+        let (timestamp, level, log_text) = RE
+            .captures(line)
+            .ok_or_else(|| anyhow!("Line is not a SteamVR log header"))?
+            .extract()
+            .1
+            .into();
+        let timestamp = NaiveDateTime::parse_from_str(timestamp, "%b %d %Y %H:%M:%S%.f")?;
+
+        Ok(Entry::new(timestamp, get_level(level), log_text))
+    }
+
+    fn session_banner(&self, entry: &Entry) -> Option<String> {
+        BANNER_RE
+            .captures(&entry.log_data())
+            .map(|captures| captures[1].to_string())
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+
+use crate::{parser::get_level, types::Entry};
+
+use super::LogFormat;
+
+/// Fallback for files that dont match any known format, such as crash dumps and other plain text.
+/// Every line becomes its own entry, stamped with the time the file was last modified
+#[derive(Debug)]
+pub struct PlainText {
+    timestamp: NaiveDateTime,
+}
+
+impl PlainText {
+    pub const fn new(timestamp: NaiveDateTime) -> Self {
+        Self { timestamp }
+    }
+}
+
+impl LogFormat for PlainText {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn detect(&self, _line: &str) -> bool {
+        true
+    }
+
+    fn parse_header(&self, line: &str) -> Result<Entry> {
+        Ok(Entry::new(self.timestamp, get_level("Text"), line))
+    }
+
+    fn for_file(&self, modified: NaiveDateTime) -> Option<Arc<dyn LogFormat>> {
+        Some(Arc::new(Self::new(modified)))
+    }
+}
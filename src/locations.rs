@@ -0,0 +1,48 @@
+use std::{env::var_os, fs, path::PathBuf};
+
+use itertools::Itertools;
+
+/// Folder of the Flatpak version of Steam, relative to the home directory
+const FLATPAK_DIR: &str = ".var/app/com.valvesoftware.Steam";
+
+/// Steam install folders that are searched for logs when no paths are given. If `STEAM_ROOT` is
+/// set only that install is used, otherwise the usual native and Flatpak locations are tried
+pub fn steam_roots() -> Vec<PathBuf> {
+    if let Some(root) = env_path("STEAM_ROOT") {
+        return vec![root];
+    }
+
+    let home = env_path("HOME");
+    let data_home =
+        env_path("XDG_DATA_HOME").or_else(|| home.as_ref().map(|home| home.join(".local/share")));
+    data_home
+        .map(|data| data.join("Steam"))
+        .into_iter()
+        .chain(home.into_iter().flat_map(|home| {
+            [
+                home.join(".steam/steam"),
+                home.join(".steam/root"),
+                home.join(FLATPAK_DIR).join(".local/share/Steam"),
+                home.join(FLATPAK_DIR).join("data/Steam"),
+            ]
+        }))
+        .collect()
+}
+
+/// Log folders of every Steam install in `steam_roots` that exists. Steam links some of these
+/// folders to each other, so each folder is only returned once
+pub fn default_log_dirs() -> Vec<PathBuf> {
+    steam_roots()
+        .into_iter()
+        .map(|root| root.join("logs"))
+        .filter(|logs| logs.is_dir())
+        .unique_by(|logs| fs::canonicalize(logs).unwrap_or_else(|_| logs.clone()))
+        .collect()
+}
+
+/// Path stored in the environment variable 'name', empty variables are treated as unset
+fn env_path(name: &str) -> Option<PathBuf> {
+    var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
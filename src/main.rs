@@ -1,72 +1,268 @@
-use std::{fs::File, io::Read, path::PathBuf, thread::spawn};
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread::{spawn, JoinHandle},
+};
 
 use anyhow::Result;
+use chrono::Local;
 use clap::Parser;
-use logwatcher::LogWatcher;
-use parser::{parse_file_path, parse_line};
+use file_filter::FileFilter;
+use format::{default_formats_path, find_format, named_formats, register_formats, LogFormat};
+use itertools::Itertools;
+use locations::{default_log_dirs, steam_roots};
+use parser::{parse_file_path, set_low_memory, unreadable_log, LogSource};
+use query::TimeBound;
 use ratatui::{backend::CrosstermBackend, Terminal};
+use stream::{read_command, read_stdin, stream_log};
 use term::{
     app::App,
+    editor::open_selected,
     event::EventHandler,
     tui::Tui,
     update::{handle_keys, handle_mouse},
 };
-use types::SharedLog;
+use time_range::{parse_bound, TimeRange};
+use types::{set_log_limits, SharedLog};
+use watcher::{load_in_background, watch_dir};
 
+mod archive;
+mod encoding;
+mod file_filter;
+mod format;
+mod locations;
 mod parser;
+mod query;
+mod stream;
 mod term;
+mod time_range;
 mod types;
+mod watcher;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Args {
-    #[arg(short, long, default_value = "txt")]
-    extension: String,
+    /// Extension of the log files to open, can be given more than once. Defaults to txt unless
+    /// --include is used
+    #[arg(short, long, value_delimiter = ',')]
+    extension: Vec<String>,
+    /// Glob pattern the log files have to match, can be given more than once. Patterns starting
+    /// with '!' exclude matching files instead
+    #[arg(short, long)]
+    include: Vec<String>,
+    /// Glob pattern for files and directories to leave out, can be given more than once
+    #[arg(short = 'x', long)]
+    exclude: Vec<String>,
+    /// How many levels of directories below the given paths are searched, 0 only opens the files
+    /// directly inside them
+    #[arg(short = 'd', long)]
+    max_depth: Option<usize>,
+    /// Log format to parse the files with, 'auto' detects the format of each file separately
+    #[arg(short, long, default_value = "auto")]
+    format: String,
+    /// Keep only where each entry is in its file rather than a copy of its text, reading it back
+    /// when it's needed. For very large logs, entries from before a file is truncated in place
+    /// can no longer be shown
+    #[arg(long)]
+    low_memory: bool,
+    /// Most entries to keep for each log, the oldest entries are dropped past this. Useful when
+    /// following logs for a long time
+    #[arg(long)]
+    max_entries: Option<usize>,
+    /// Most text to keep for each log, like 512K or 64M. The oldest entries are dropped past this
+    #[arg(long, value_parser = parse_size)]
+    max_bytes: Option<usize>,
+    /// Only show entries at or after this time. Takes a time like 10:15, a date, a date and time
+    /// like '2024-07-12 10:15', or a duration like 10m for that long ago
+    #[arg(long, value_parser = parse_time)]
+    since: Option<TimeBound>,
+    /// Only show entries at or before this time, takes the same values as --since
+    #[arg(long, value_parser = parse_time)]
+    until: Option<TimeBound>,
+    /// Number of entries to show before and after each entry that passes the filter, like grep -C
+    #[arg(short = 'C', long, default_value_t = 0)]
+    context: usize,
+    /// TOML file describing extra log formats, defaults to formats.toml in the config directory
+    #[arg(long)]
+    formats: Option<PathBuf>,
+    /// Command to run, its output is read as a log while it runs. Can be given more than once
+    #[arg(long)]
+    cmd: Vec<String>,
+    /// Log files or directories to open, '-' reads a log from stdin. Without any the logs of the
+    /// local Steam install are opened
     #[arg(num_args = 1..)]
     files: Vec<PathBuf>,
 }
 
+/// Parse a size in bytes, optionally followed by a K, M or G suffix
+fn parse_size(size: &str) -> Result<usize, String> {
+    let size = size.trim();
+    let (number, multiplier) = match size.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&size[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&size[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+    number
+        .trim()
+        .parse::<usize>()
+        .map_err(|err| err.to_string())?
+        .checked_mul(multiplier)
+        .ok_or_else(|| "size is too large".to_string())
+}
+
+/// Open every log asked for in 'args', starting the threads that keep them up to date. Logs found
+/// in the given paths, and later on in watched directories, are sent through the returned receiver.
+/// The returned thread is the one searching the given paths, it finishes once they have been
+/// searched
+fn open_logs(
+    args: &Args,
+    filter: &FileFilter,
+    format: Option<&Arc<dyn LogFormat>>,
+) -> (Vec<SharedLog>, Receiver<SharedLog>, JoinHandle<()>) {
+    let (stdin, paths): (Vec<_>, Vec<_>) =
+        args.files.iter().partition(|path| path.as_os_str() == "-");
+
+    // Streams are read on their own threads for as long as they stay open
+    let detect = format.is_none();
+    let mut logs = Vec::new();
+    if !stdin.is_empty() {
+        let log = stream_log("stdin", format);
+        logs.push(log.clone());
+        spawn(move || read_stdin(&log, detect));
+    }
+    for command in &args.cmd {
+        let log = stream_log(command, format);
+        logs.push(log.clone());
+        let command = command.clone();
+        spawn(move || read_command(&command, &log, detect));
+    }
+
+    // Searching folders with large archives in them can take a while, so the paths are searched
+    // in the background and each log is sent to the UI once it has been found
+    let (new_logs, receiver) = channel();
+    let paths = paths.into_iter().cloned().collect_vec();
+    let filter = filter.clone();
+    let format = format.cloned();
+    let search = spawn(move || find_logs(&paths, &filter, format.as_ref(), &new_logs));
+
+    (logs, receiver, search)
+}
+
+/// Search 'paths' for logs and send each one through 'sender', then read their existing contents
+/// in the background. Afterwards the newest file of each log is followed and directories are
+/// watched for log files created after startup
+fn find_logs(
+    paths: &[PathBuf],
+    filter: &FileFilter,
+    format: Option<&Arc<dyn LogFormat>>,
+    sender: &Sender<SharedLog>,
+) {
+    let mut found = Vec::new();
+    for path in paths {
+        let logs = match parse_file_path(path, filter, format) {
+            Ok(logs) => logs,
+            Err(err) => {
+                // Paths that couldnt be read at all still get listed so the problem can be seen
+                // in the UI
+                if sender.send(unreadable_log(path, &err)).is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+        for (log, _) in &logs {
+            if sender.send(log.clone()).is_err() {
+                return;
+            }
+        }
+        if path.is_dir() {
+            let dir = path.clone();
+            let known: HashSet<LogSource> = logs
+                .iter()
+                .flat_map(|(_, sources)| sources.iter().cloned())
+                .collect();
+            let filter = filter.clone();
+            let format = format.cloned();
+            let sender = sender.clone();
+            spawn(move || watch_dir(&dir, &filter, format.as_ref(), known, &sender));
+        }
+        found.extend(logs);
+    }
+    load_in_background(found, format.is_none());
+}
+
+/// Parse a time for --since or --until, durations are taken back from the current time
+fn parse_time(time: &str) -> Result<TimeBound, String> {
+    parse_bound(time, Local::now().naive_local()).map_err(|err| format!("{err:#}"))
+}
+
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    // Without any paths the logs of the local Steam install are opened instead. Where they were
+    // looked for is shown in the UI, or printed if none were found
+    let mut notes = Vec::new();
+    if args.files.is_empty() && args.cmd.is_empty() {
+        notes.push("No paths given, searched for Steam logs in:".to_string());
+        for logs in steam_roots().into_iter().map(|root| root.join("logs")) {
+            let found = if logs.is_dir() { "found" } else { "not found" };
+            notes.push(format!("  {} ({found})", logs.display()));
+        }
+        args.files = default_log_dirs();
+        if args.files.is_empty() {
+            println!("{}", notes.join("\n"));
+            println!(
+                "No Steam logs found, pass the path to the log files or set STEAM_ROOT to the \
+                 Steam install to use"
+            );
+            return Ok(());
+        }
+    }
+
+    // A formats file given on the command line has to load, the default one is optional
+    if let Some(path) = &args.formats {
+        register_formats(path)?;
+    } else if let Some(path) = default_formats_path().filter(|path| path.is_file()) {
+        register_formats(&path)?;
+    }
+
+    set_low_memory(args.low_memory);
+    set_log_limits(args.max_entries, args.max_bytes);
+    let filter = FileFilter::new(
+        &args.extension,
+        &args.include,
+        &args.exclude,
+        args.max_depth,
+    )?;
+
+    let format = if args.format.eq_ignore_ascii_case("auto") {
+        None
+    } else if let Some(format) = find_format(&args.format) {
+        Some(format)
+    } else {
+        println!(
+            "Unknown log format '{}', expected auto or one of: {}",
+            args.format,
+            named_formats()
+                .iter()
+                .map(|format| format.name())
+                .join(", ")
+        );
+        return Ok(());
+    };
 
-    if args.files.is_empty() {
-        println!("Path to log files is required");
+    let time_range = TimeRange::new(args.since, args.until);
+    if let Err(err) = time_range.check() {
+        println!("Invalid time range: {err}");
         return Ok(());
     }
 
-    let (logs, paths): (Vec<SharedLog>, Vec<PathBuf>) = args
-        .files
-        .iter()
-        .flat_map(|path| parse_file_path(path, &args.extension).unwrap())
-        .map(|(log, path)| {
-            // Read the existing contents of the file into the log
-            let log_new = log.clone();
-            let mut log_mut = log_new.lock().unwrap();
-            let mut output = String::new();
-            File::open(&path)
-                .unwrap()
-                .read_to_string(&mut output)
-                .unwrap();
-            output
-                .lines()
-                .for_each(|line| parse_line(&mut log_mut, line).unwrap());
-            (log, path)
-        })
-        .unzip();
-
-    // Creating new threads to handle the log watching
-    paths
-        .into_iter()
-        .zip(logs.iter().cloned())
-        .for_each(|(path, log)| {
-            spawn(|| {
-                let mut watcher = LogWatcher::register(path).unwrap();
-                watcher.watch(&mut move |line: String| {
-                    parse_line(&mut log.lock().unwrap(), &line).unwrap();
-                    logwatcher::LogWatcherAction::None
-                });
-            });
-        });
+    let (logs, new_logs, search) = open_logs(&args, &filter, format.as_ref());
 
     // Init term ui
     let backend = CrosstermBackend::new(std::io::stderr());
@@ -76,9 +272,19 @@ fn main() -> Result<()> {
     tui.enter()?;
 
     let mut app = App::new(logs);
+    app.time_range = time_range;
+    app.context = args.context;
+    app.notes = notes;
 
     // Do main program loop
     while !app.should_quit {
+        // Checked first so that every log the search found has been received once it's finished
+        let searching = !search.is_finished();
+        for log in new_logs.try_iter() {
+            app.add_log(log);
+        }
+        app.searching = searching;
+        app.mark_seen();
         tui.draw(&mut app)?;
 
         match tui.events.next().unwrap() {
@@ -86,6 +292,11 @@ fn main() -> Result<()> {
             term::event::Event::Mouse(mouse_event) => handle_mouse(&mut app, mouse_event),
             _ => {}
         }
+
+        if app.open_in_editor {
+            app.open_in_editor = false;
+            open_selected(&mut tui, &app)?;
+        }
     }
 
     // Close down the term ui stuff cleanly
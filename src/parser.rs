@@ -1,20 +1,41 @@
 use std::{
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    collections::HashMap,
+    fs::File,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
-use anyhow::{anyhow, Result};
-use chrono::NaiveDate;
-use once_cell::sync::Lazy;
-use regex::Regex;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, NaiveDateTime};
+use itertools::Itertools;
 
-use crate::types::{Entry, Log, SharedLog};
+use crate::{
+    archive::{list_members, read_member, ArchiveKind},
+    encoding::Encoding,
+    file_filter::FileFilter,
+    format::{detect_format, Console, LogFormat, PlainText},
+    types::{Log, SharedLog},
+};
+
+/// How many lines from the start of a file are checked when detecting its format
+const SNIFF_LINES: usize = 50;
+/// Upper limit on how much of a file is read to find those lines
+const SNIFF_BYTES: u64 = 64 * 1024;
+/// How many bytes of a file are read and parsed at once while it's loaded
+const LOAD_CHUNK: usize = 1024 * 1024;
+
+/// Whether entries read from files keep where their text is in the file rather than a copy of it
+static LOW_MEMORY: AtomicBool = AtomicBool::new(false);
 
 static CACHE: Mutex<Vec<Arc<str>>> = Mutex::new(Vec::new());
 
 /// If 'input' is present in the cache then return a copy of that Arc, otherwise create and add a
 /// new one to the cache
-fn get_level(input: &str) -> Arc<str> {
+pub fn get_level(input: &str) -> Arc<str> {
     let input = format!("{input:^9}");
     let mut cache = CACHE.lock().unwrap();
     #[allow(clippy::option_if_let_else)] // The suggested implentation from clippy doesnt actually
@@ -33,87 +54,516 @@ pub fn get_levels() -> Vec<Arc<str>> {
     CACHE.lock().unwrap().clone()
 }
 
-/// Parse a log entry into an `Entry` object with timestamp, level, and data
-fn parse(log_line: [&str; 3]) -> Result<Entry> {
-    static TIME_RE: Lazy<Regex> =
-        Lazy::new(|| Regex::new(r"(\d{2}):(\d{2}):(\d{2}).(\d+)").unwrap());
-    let (timestamp, level, log_text) = log_line.into();
-    // Split the timestamp into date and time separately
-    let (date, time) = timestamp
-        .split_once(' ') // We dont need the day of the week so we're discarding it here
-        .ok_or_else(|| anyhow!("Failed to split timestamp"))?
-        .1
-        .split_at(12); // This is where we split the time and date
-                       // Use regex to parse out the components from the time portion of the timestamp
-    let (hour, min, sec, micro) = TIME_RE
-        .captures(time)
-        .ok_or_else(|| anyhow!("Failed to parse time"))?
-        .extract()
-        .1
-        .into();
-    // I dont mind unwrapping these because the regex already confirmed that they're digits of
-    // acceptable length
-    let (hour, min, sec, micro) = (
-        hour.parse().unwrap(),
-        min.parse().unwrap(),
-        sec.parse().unwrap(),
-        micro.parse().unwrap(),
-    );
-    // First we let the parse_from_str function parse the date from the date string, then we add on
-    // the time component from the parts we got with the regex
-    let timestamp = NaiveDate::parse_from_str(date.trim(), "%b %d %Y")?
-        .and_hms_micro_opt(hour, min, sec, micro)
-        .ok_or_else(|| anyhow!("Failed to build DateTime object"))?;
+// Parses an input line and adds it to the `SharedLog`. Creates a new `Entry` if required or
+// appends the data to the previous entry if appropreate. How the line is interpreted is up to the
+// `LogFormat` assigned to the log. 'offset' is where the line starts in the file.
+// Any problems are recorded as diagnostics on the log rather than returned.
+pub fn parse_line(log: &mut Log, line: &str, offset: u64) {
+    log.next_line(offset);
+    let format = log.format().clone();
+    // If it's a header we pass it through to the format to parse, otherwise we append to the
+    // previous log entry
+    if format.detect(line) {
+        match format.parse_header(line) {
+            Ok(entry) => {
+                if let Some(label) = format.session_banner(&entry) {
+                    log.start_session(&label);
+                }
+                log.add_entry(entry, line);
+            }
+            Err(err) => {
+                // Keep the text around so nothing is lost, it's still shown with the entry before
+                log.add_diagnostic(line, format!("Failed to parse header: {err}"));
+                log.append_last(line);
+            }
+        }
+    } else if format.is_continuation(line) {
+        log.append_last(line);
+    } else {
+        log.add_diagnostic(
+            line,
+            format!(
+                "Skipped a line that isnt a header or a continuation line in the '{}' format",
+                format.name()
+            ),
+        );
+    }
+}
+
+/// Where the contents of a log are read from
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogSource {
+    /// A file on disk, this is followed for new lines once it has been read
+    File(PathBuf),
+    /// A file stored inside an archive, only read once
+    Archive {
+        archive: PathBuf,
+        kind: ArchiveKind,
+        member: PathBuf,
+    },
+}
+
+impl LogSource {
+    /// Path of the file, relative to the archive for files inside one
+    fn path(&self) -> &Path {
+        match self {
+            Self::File(path) => path,
+            Self::Archive { member, .. } => member,
+        }
+    }
+
+    /// The same kind of source, in the same archive if there is one, but for a different file
+    fn with_path(&self, path: PathBuf) -> Self {
+        match self {
+            Self::File(_) => Self::File(path),
+            Self::Archive { archive, kind, .. } => Self::Archive {
+                archive: archive.clone(),
+                kind: *kind,
+                member: path,
+            },
+        }
+    }
+
+    /// Name the log is shown with, files inside archives use their whole path in the archive
+    pub fn name(&self) -> String {
+        match self {
+            Self::File(path) => path.file_name().unwrap().to_string_lossy().into_owned(),
+            Self::Archive { member, .. } => member.to_string_lossy().into_owned(),
+        }
+    }
+
+    /// Path of the file on disk, if it's one that can be followed
+    pub fn followed_path(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) => Some(path),
+            Self::Archive { .. } => None,
+        }
+    }
+
+    /// Read the contents of the source, at most 'limit' bytes of it if that is set
+    fn read(&self, limit: Option<u64>) -> Result<Vec<u8>> {
+        match self {
+            Self::File(path) => {
+                let mut bytes = Vec::new();
+                File::open(path)?
+                    .take(limit.unwrap_or(u64::MAX))
+                    .read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            Self::Archive {
+                archive,
+                kind,
+                member,
+            } => read_member(archive, *kind, member, limit),
+        }
+    }
+
+    /// Open the contents of the source for reading, along with their length. Files inside archives
+    /// have to be decompressed into memory first
+    fn open(&self) -> Result<(Box<dyn Read>, u64)> {
+        match self {
+            Self::File(path) => {
+                let file = File::open(path)?;
+                let len = file.metadata()?.len();
+                Ok((Box::new(file), len))
+            }
+            Self::Archive { .. } => {
+                let bytes = self.read(None)?;
+                let len = bytes.len() as u64;
+                Ok((Box::new(Cursor::new(bytes)), len))
+            }
+        }
+    }
+
+    /// Size of the file on disk, used to load small logs first. Files inside archives count as
+    /// empty since finding their size means reading the archive
+    pub fn size_hint(&self) -> u64 {
+        match self {
+            Self::File(path) => path.metadata().map_or(0, |metadata| metadata.len()),
+            Self::Archive { .. } => 0,
+        }
+    }
 
-    let level = get_level(level);
+    /// Last modified time of the file, or of the archive it is stored in
+    fn modified(&self) -> Result<NaiveDateTime> {
+        let path = match self {
+            Self::File(path) => path,
+            Self::Archive { archive, .. } => archive,
+        };
+        Ok(DateTime::<Local>::from(path.metadata()?.modified()?).naive_local())
+    }
+}
 
-    Ok(Entry::new(timestamp, level, log_text))
+/// Decode 'bytes' using the encoding of 'log' and parse every line in them. The bytes have to
+/// carry on directly from the ones parsed before, so that the file offset of each line is known
+pub fn parse_bytes(log: &mut Log, bytes: &[u8]) {
+    let encoding = log.encoding().unwrap_or(Encoding::Utf8);
+    let start = log.file_position();
+    for (offset, line) in encoding.split_lines(bytes) {
+        let line = encoding.decode(line);
+        parse_line(log, &line, start + offset as u64);
+        if line.contains(char::REPLACEMENT_CHARACTER) {
+            log.add_diagnostic(&line, format!("Invalid {encoding} text was replaced"));
+        }
+    }
+    log.set_file_position(start + bytes.len() as u64);
 }
 
-// Parses an input line and adds it to the `SharedLog`. Creates a new `Entry` if required or
-// appends the data to the previous entry if appropreate.
-pub fn parse_line(log: &mut Log, line: &str) -> Result<()> {
-    static RE: Lazy<Regex> = Lazy::new(|| {
-        Regex::new(r"(\w{3} \w{3} \d{2} \d{4} \d{2}:\d{2}:\d{2}.\d+) \[(\w+?)\] - (.+?)$").unwrap()
+/// Turn on low memory mode, where entries read from files keep ranges of the file rather than
+/// copies of their text. This keeps memory use for very large logs well below the size of the file.
+/// The file is read back with ordinary reads rather than being memory mapped, since mapping it
+/// would need unsafe code and a file that is truncated while mapped crashes the program
+pub fn set_low_memory(enabled: bool) {
+    LOW_MEMORY.store(enabled, Ordering::Relaxed);
+}
+
+/// Read the current contents of 'source' into 'log', a chunk at a time so that the log is only
+/// locked for short periods while it loads and the file is never held in memory as a whole.
+/// 'progress' is called with the share of the file read so far. If the file is going to be followed
+/// a last line that is still being written is left out, and the returned position is the end of
+/// the last complete line so that following the file can carry on from there
+fn read_source(
+    log: &SharedLog,
+    source: &LogSource,
+    followed: bool,
+    progress: impl Fn(u64, u64) -> u8,
+) -> u64 {
+    let opened = source.open().and_then(|(reader, len)| {
+        // Files inside archives cant be read back from, so they are always copied. Entries get a
+        // handle of their own so that reading them back doesnt move the one being loaded from
+        let stored = match source.followed_path() {
+            Some(path) if LOW_MEMORY.load(Ordering::Relaxed) => Some(Arc::new(File::open(path)?)),
+            _ => None,
+        };
+        Ok((reader, len, stored))
     });
-    // If it's a match we pass it through to the parse function, otherwise we append to the
-    // previous log entry
-    if let Some(captures) = RE.captures(line) {
-        let entry = parse(captures.extract().1)?;
-        log.add_entry(entry);
-        Ok(())
+    let (reader, len, stored) = match opened {
+        Ok(opened) => opened,
+        Err(err) => {
+            log.lock()
+                .unwrap()
+                .add_file_diagnostic(format!("Failed to read file: {err}"));
+            return 0;
+        }
+    };
+
+    // Anything written after the file was opened is left for following it to pick up
+    let mut reader = reader.take(len);
+    let mut pending = Vec::new();
+    let mut position = 0;
+    let mut encoding = None;
+    loop {
+        let scanned = pending.len();
+        let read = match (&mut reader)
+            .take(LOAD_CHUNK as u64)
+            .read_to_end(&mut pending)
+        {
+            Ok(read) => read,
+            Err(err) => {
+                log.lock()
+                    .unwrap()
+                    .add_file_diagnostic(format!("Failed to read file: {err}"));
+                break;
+            }
+        };
+        // The encoding is worked out from the first chunk
+        let encoding = *encoding.get_or_insert_with(|| {
+            let encoding = Encoding::detect(&pending);
+            let mut log = log.lock().unwrap();
+            log.set_encoding(encoding);
+            log.set_stored(stored.clone());
+            encoding
+        });
+
+        // Chunks end on a line boundary, so a line that is longer than a whole chunk is read on
+        // until its newline. Only the bytes just read can hold one, the rest is a partial line
+        let done = read == 0;
+        let complete = match encoding.complete_lines_len(&pending[scanned..]) {
+            _ if done && !followed => pending.len(),
+            0 => 0,
+            complete => scanned + complete,
+        };
+        if complete > 0 {
+            let rest = pending.split_off(complete);
+            position += complete as u64;
+            let mut log = log.lock().unwrap();
+            parse_bytes(&mut log, &pending);
+            log.set_loading(Some(progress(position, len)));
+            drop(log);
+            pending = rest;
+        }
+        if done {
+            break;
+        }
+    }
+    log.lock().unwrap().set_stored(None);
+    position
+}
+
+/// Build an empty log for a path that couldnt be opened, so that the problem still shows up in the
+/// UI next to the logs that did load
+pub fn unreadable_log(path: &Path, err: &anyhow::Error) -> SharedLog {
+    let mut log = Log::new(
+        &path.display(),
+        Arc::new(PlainText::new(NaiveDateTime::default())),
+    );
+    log.add_file_diagnostic(format!("Failed to open: {err:#}"));
+    Arc::new(Mutex::new(log))
+}
+
+/// Read the first few lines of 'source' and work out which format it's written in. Files that dont
+/// match any known format are shown as plain text. A console.log from a Source game often has no
+/// timestamps to detect it by, so it's recognised by its name instead
+fn sniff_format(source: &LogSource) -> Result<Arc<dyn LogFormat>> {
+    let is_console = source
+        .path()
+        .file_name()
+        .is_some_and(|name| name.eq_ignore_ascii_case("console.log"));
+    if is_console {
+        return Ok(Arc::new(Console::new(source.modified()?)));
+    }
+    let sample = source.read(Some(SNIFF_BYTES))?;
+    if let Some(format) = sniff_sample(&sample) {
+        Ok(format)
     } else {
-        log.append_last(line)
+        Ok(Arc::new(PlainText::new(source.modified()?)))
     }
 }
 
-pub fn parse_file_path<T: Into<PathBuf>>(
-    path: T,
-    extension: &str,
-) -> Result<Vec<(SharedLog, PathBuf)>> {
-    let path: PathBuf = path.into();
+/// Work out which known format the first few lines of 'sample' are written in, if any
+pub fn sniff_sample(sample: &[u8]) -> Option<Arc<dyn LogFormat>> {
+    let lines = Encoding::detect(sample)
+        .decode(sample)
+        .lines()
+        .take(SNIFF_LINES)
+        .map(str::to_string)
+        .collect_vec();
+    detect_format(&lines)
+}
 
-    let _ = path.try_exists()?;
-
-    if path.is_file()
-        && path
-            .extension()
-            .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
-    {
-        Ok(vec![(
-            Arc::new(Mutex::new(Log::new(
-                &path.file_name().unwrap().to_string_lossy(),
-            ))),
-            path,
-        )])
-    } else if path.is_dir() {
+/// Collect every file at 'path' that 'filter' accepts, searching directories and archives
+/// recursively. Archives are only searched if 'search' returns true for their path. Fails if
+/// nothing exists at 'path', so that a mistyped path is reported rather than just finding nothing
+pub fn find_sources(
+    path: &Path,
+    filter: &FileFilter,
+    search: &mut dyn FnMut(&Path) -> bool,
+) -> Result<Vec<LogSource>> {
+    if !path.try_exists()? {
+        bail!("Nothing exists at this path");
+    }
+    find_sources_at(path, filter, search, 0)
+}
+
+fn find_sources_at(
+    path: &Path,
+    filter: &FileFilter,
+    search: &mut dyn FnMut(&Path) -> bool,
+    depth: usize,
+) -> Result<Vec<LogSource>> {
+    if path.is_file() {
+        if let Some(kind) = ArchiveKind::from_path(path) {
+            if filter.excludes(path) || !search(path) {
+                return Ok(vec![]);
+            }
+            Ok(list_members(path, kind, |member| filter.matches(member))?
+                .into_iter()
+                .map(|member| LogSource::Archive {
+                    archive: path.to_path_buf(),
+                    kind,
+                    member,
+                })
+                .collect())
+        } else if filter.matches(path) {
+            Ok(vec![LogSource::File(path.to_path_buf())])
+        } else {
+            Ok(vec![])
+        }
+    } else if path.is_dir() && filter.within_depth(depth) && !filter.excludes(path) {
         Ok(path
             .read_dir()?
             .flatten()
-            .flat_map(|entry| parse_file_path(entry.path(), extension))
+            .flat_map(|entry| find_sources_at(&entry.path(), filter, search, depth + 1))
             .flatten()
             .collect())
     } else {
         Ok(vec![])
     }
 }
+
+/// `SteamVR` renames the log from its last run to `<name>.previous.<ext>` when it starts. If 'path'
+/// is one of these files, return the path of the current log it was rotated from
+fn rotated_from(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let extension = path.extension()?.to_str()?;
+    let stem = name.strip_suffix(&format!(".previous.{extension}"))?;
+    Some(path.with_file_name(format!("{stem}.{extension}")))
+}
+
+/// The source that 'source' was rotated from, if it's a rotated log
+pub fn rotated_source(source: &LogSource) -> Option<LogSource> {
+    rotated_from(source.path()).map(|path| source.with_path(path))
+}
+
+/// Group 'sources' so that rotated logs are paired up with the current log they were rotated
+/// from. Each group is in chronological order, so the file that's still being written to is last
+pub fn group_rotations(mut sources: Vec<LogSource>) -> Vec<Vec<LogSource>> {
+    sources.sort();
+    let previous: HashMap<LogSource, LogSource> = sources
+        .iter()
+        .filter_map(|source| {
+            rotated_source(source)
+                .filter(|current| sources.contains(current))
+                .map(|current| (current, source.clone()))
+        })
+        .collect();
+    sources
+        .into_iter()
+        .filter(|source| {
+            !rotated_source(source).is_some_and(|current| previous.contains_key(&current))
+        })
+        .map(|source| match previous.get(&source) {
+            Some(rotated) => vec![rotated.clone(), source],
+            None => vec![source],
+        })
+        .collect()
+}
+
+/// Build an empty `Log` for every file at 'path' that 'filter' accepts, along with the sources
+/// that should be loaded into it. Archives are searched for matching files as well
+pub fn parse_file_path<T: Into<PathBuf>>(
+    path: T,
+    filter: &FileFilter,
+    format: Option<&Arc<dyn LogFormat>>,
+) -> Result<Vec<(SharedLog, Vec<LogSource>)>> {
+    let path: PathBuf = path.into();
+
+    Ok(group_rotations(find_sources(&path, filter, &mut |_| true)?)
+        .into_iter()
+        .map(|sources| (open_log(&sources, format), sources))
+        .collect())
+}
+
+/// Build an empty `Log` for a group of rotated 'sources', to be filled in by `load_sources`. If
+/// 'format' is `None` the log is shown as plain text until the format has been detected
+pub fn open_log(sources: &[LogSource], format: Option<&Arc<dyn LogFormat>>) -> SharedLog {
+    let format = format.cloned().unwrap_or_else(|| {
+        Arc::new(PlainText::new(NaiveDateTime::default())) as Arc<dyn LogFormat>
+    });
+    let mut log = Log::new(&sources.last().unwrap().name(), format);
+    log.set_path(sources.last().unwrap().followed_path());
+    log.set_loading(Some(0));
+    Arc::new(Mutex::new(log))
+}
+
+/// Read the existing contents of 'sources' into 'log'. Rotated logs come first and each file gets
+/// its own session. If 'detect' is set the format is detected from the current file first, since
+/// that is the one that will be followed. Returns the number of bytes read from the current file
+pub fn load_sources(log: &SharedLog, sources: &[LogSource], detect: bool) -> u64 {
+    if detect {
+        match sniff_format(sources.last().unwrap()) {
+            Ok(format) => log.lock().unwrap().set_format(format),
+            Err(err) => {
+                let mut log = log.lock().unwrap();
+                log.add_file_diagnostic(format!("Failed to open: {err:#}"));
+                log.set_loading(None);
+                drop(log);
+                return 0;
+            }
+        }
+    } else {
+        // The format was given on the command line and is shared by every log, so formats that
+        // depend on the file get a copy of their own for it
+        let format = log.lock().unwrap().format().clone();
+        let copy = sources
+            .last()
+            .unwrap()
+            .modified()
+            .ok()
+            .and_then(|modified| format.for_file(modified));
+        if let Some(format) = copy {
+            log.lock().unwrap().set_format(format);
+        }
+    }
+
+    let mut position = 0;
+    for (i, source) in sources.iter().enumerate() {
+        if sources.len() > 1 {
+            log.lock()
+                .unwrap()
+                .start_file(&source.name(), source.followed_path());
+        }
+        let followed = i + 1 == sources.len() && source.followed_path().is_some();
+        // Each file counts for an equal share of the progress
+        position = read_source(log, source, followed, |read, len| {
+            let percent = (i as u64 * 100 + read * 100 / len.max(1)) / sources.len() as u64;
+            u8::try_from(percent).unwrap_or(100)
+        });
+    }
+    log.lock().unwrap().set_loading(None);
+    position
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(names: &[&str]) -> Vec<LogSource> {
+        names
+            .iter()
+            .map(|name| LogSource::File(Path::new("/logs").join(name)))
+            .collect()
+    }
+
+    #[test]
+    fn rotated_logs_are_grouped_before_the_current_log() {
+        let groups = group_rotations(files(&[
+            "vrserver.txt",
+            "vrmonitor.txt",
+            "vrserver.previous.txt",
+        ]));
+        assert_eq!(
+            groups,
+            vec![
+                files(&["vrmonitor.txt"]),
+                files(&["vrserver.previous.txt", "vrserver.txt"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rotated_logs_without_a_current_log_stay_on_their_own() {
+        let groups = group_rotations(files(&["vrcompositor.previous.txt", "vrserver.txt"]));
+        assert_eq!(
+            groups,
+            vec![
+                files(&["vrcompositor.previous.txt"]),
+                files(&["vrserver.txt"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rotated_logs_are_only_grouped_within_the_same_archive() {
+        let member = |archive: &str, member: &str| LogSource::Archive {
+            archive: PathBuf::from(archive),
+            kind: ArchiveKind::Zip,
+            member: PathBuf::from(member),
+        };
+        let groups = group_rotations(vec![
+            member("a.zip", "vrserver.txt"),
+            member("b.zip", "vrserver.previous.txt"),
+            member("a.zip", "vrserver.previous.txt"),
+        ]);
+        assert_eq!(
+            groups,
+            vec![
+                vec![
+                    member("a.zip", "vrserver.previous.txt"),
+                    member("a.zip", "vrserver.txt"),
+                ],
+                vec![member("b.zip", "vrserver.previous.txt")],
+            ]
+        );
+    }
+}
@@ -0,0 +1,483 @@
+use std::{
+    cmp::Ordering, fmt::Display, iter::Peekable, ops::Range, str::CharIndices, vec::IntoIter,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+
+use crate::types::Entry;
+
+/// A filter typed into the filter box in query mode, such as
+/// `level:error file:vrserver "lighthouse" AND NOT /timeout \d+/ after:10:15`
+#[derive(Debug)]
+pub enum Query {
+    /// Entries with a level that starts with the text, ignoring case
+    Level(String),
+    /// Entries from logs with a name that contains the text, ignoring case
+    File(String),
+    /// Entries that contain the text
+    Text(String),
+    Regex(Regex),
+    /// Entries at or after the time
+    After(TimeBound),
+    /// Entries before the time
+    Before(TimeBound),
+    Not(Box<Self>),
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+}
+
+impl Query {
+    /// Parse 'input', terms next to each other without AND or OR between them must all match.
+    /// Returns `None` if the input is blank
+    pub fn parse(input: &str) -> Result<Option<Self>> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+            input,
+        };
+        let query = parser.or()?;
+        if let Some((position, _)) = parser.tokens.next() {
+            bail!("Unexpected ')' at column {}", column(input, position));
+        }
+        Ok(Some(query))
+    }
+
+    /// Returns true if 'entry', from the log with the given name, matches the query
+    pub fn matches(&self, log_name: &str, entry: &Entry) -> bool {
+        match self {
+            Self::Level(level) => starts_with_ignore_case(entry.log_level().trim(), level),
+            Self::File(name) => log_name.to_lowercase().contains(&name.to_lowercase()),
+            Self::Text(text) => entry.log_data().contains(text.as_str()),
+            Self::Regex(re) => re.is_match(&entry.log_data()),
+            Self::After(bound) => bound.compare(entry.timestamp()) != Ordering::Less,
+            Self::Before(bound) => bound.compare(entry.timestamp()) == Ordering::Less,
+            Self::Not(query) => !query.matches(log_name, entry),
+            Self::And(left, right) => {
+                left.matches(log_name, entry) && right.matches(log_name, entry)
+            }
+            Self::Or(left, right) => {
+                left.matches(log_name, entry) || right.matches(log_name, entry)
+            }
+        }
+    }
+
+    /// Add the byte ranges of 'text' matched by the text and regex terms of the query to
+    /// 'ranges'. Terms under a NOT dont match anything that is shown so they're left out
+    pub fn highlights(&self, text: &str, ranges: &mut Vec<Range<usize>>) {
+        match self {
+            Self::Text(needle) => ranges.extend(text_ranges(text, needle)),
+            Self::Regex(re) => ranges.extend(regex_ranges(re, text)),
+            Self::And(left, right) | Self::Or(left, right) => {
+                left.highlights(text, ranges);
+                right.highlights(text, ranges);
+            }
+            Self::Level(_) | Self::File(_) | Self::After(_) | Self::Before(_) | Self::Not(_) => {}
+        }
+    }
+}
+
+/// Byte ranges of each place 'needle' is found in 'text'
+pub fn text_ranges<'a>(text: &'a str, needle: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+    text.match_indices(needle)
+        .filter(|(_, found)| !found.is_empty())
+        .map(|(start, found)| start..start + found.len())
+}
+
+/// Byte ranges of each match of 're' in 'text'. If the regex has capture groups only the groups
+/// are returned, so that a pattern can pick out the part of the match that matters
+pub fn regex_ranges<'a>(re: &'a Regex, text: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+    let first_group = usize::from(re.captures_len() > 1);
+    re.captures_iter(text).flat_map(move |captures| {
+        (first_group..captures.len())
+            .filter_map(|group| captures.get(group))
+            .map(|found| found.range())
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>()
+    })
+}
+
+/// A point in time for `after:` and `before:`. A time on its own is compared with the time of day
+/// of each entry, and a date on its own with the day
+#[derive(Clone, Copy, Debug)]
+pub enum TimeBound {
+    Time(NaiveTime),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+impl TimeBound {
+    /// Accepts `10:15`, `10:15:30.5`, `2024-07-12`, and a date and time joined by a space or 'T'
+    pub fn parse(text: &str) -> Option<Self> {
+        const TIMES: [&str; 2] = ["%H:%M:%S%.f", "%H:%M"];
+        let text = text.trim();
+        if let Some(time) = TIMES
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(text, format).ok())
+        {
+            return Some(Self::Time(time));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            return Some(Self::Date(date));
+        }
+        TIMES
+            .iter()
+            .find_map(|format| {
+                [' ', 'T'].into_iter().find_map(|separator| {
+                    NaiveDateTime::parse_from_str(text, &format!("%Y-%m-%d{separator}{format}"))
+                        .ok()
+                })
+            })
+            .map(Self::DateTime)
+    }
+
+    /// How 'timestamp' compares to the bound, only looking at the parts of it the bound has
+    pub fn compare(&self, timestamp: &NaiveDateTime) -> Ordering {
+        match self {
+            Self::Time(time) => timestamp.time().cmp(time),
+            Self::Date(date) => timestamp.date().cmp(date),
+            Self::DateTime(date_time) => timestamp.cmp(date_time),
+        }
+    }
+}
+
+impl Display for TimeBound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Time(time) => write!(f, "{}", time.format("%H:%M:%S")),
+            Self::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            Self::DateTime(date_time) => write!(f, "{}", date_time.format("%Y-%m-%d %H:%M:%S")),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Term(Query),
+}
+
+struct Parser<'a> {
+    /// Tokens along with the byte offset in the input they start at
+    tokens: Peekable<IntoIter<(usize, Token)>>,
+    input: &'a str,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Query> {
+        let mut query = self.and()?;
+        while self.next_if(|token| matches!(token, Token::Or)) {
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query> {
+        let mut query = self.not()?;
+        loop {
+            match self.tokens.peek() {
+                None | Some((_, Token::Close | Token::Or)) => return Ok(query),
+                Some((_, Token::And)) => {
+                    self.tokens.next();
+                }
+                Some(_) => {}
+            }
+            query = Query::And(Box::new(query), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Query> {
+        if self.next_if(|token| matches!(token, Token::Not)) {
+            Ok(Query::Not(Box::new(self.not()?)))
+        } else {
+            self.term()
+        }
+    }
+
+    fn term(&mut self) -> Result<Query> {
+        let Some((position, token)) = self.tokens.next() else {
+            bail!("Expected a search term at the end");
+        };
+        let column = column(self.input, position);
+        match token {
+            Token::Term(query) => Ok(query),
+            Token::Open => {
+                let query = self.or()?;
+                if !self.next_if(|token| matches!(token, Token::Close)) {
+                    bail!("Unclosed '(' at column {column}");
+                }
+                Ok(query)
+            }
+            Token::Close => bail!("Unexpected ')' at column {column}"),
+            Token::And | Token::Or => bail!("Expected a search term at column {column}"),
+            Token::Not => unreachable!("NOT is handled before terms"),
+        }
+    }
+
+    /// Consume the next token if 'test' returns true for it
+    fn next_if(&mut self, test: impl FnOnce(&Token) -> bool) -> bool {
+        self.tokens.next_if(|(_, token)| test(token)).is_some()
+    }
+}
+
+/// Split 'input' into tokens, building the search terms as it goes
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        let token = match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' => {
+                chars.next();
+                Token::Open
+            }
+            ')' => {
+                chars.next();
+                Token::Close
+            }
+            '"' => Token::Term(Query::Text(quoted(input, &mut chars)?)),
+            '/' => {
+                chars.next();
+                let pattern = delimited(&mut chars, '/').with_context(|| {
+                    format!("Unclosed regex at column {}", column(input, start))
+                })?;
+                // Regex errors point at the problem over several lines, only the last one
+                // saying what it is fits in the filter title
+                Token::Term(Query::Regex(Regex::new(&pattern).map_err(|err| {
+                    let err = err.to_string();
+                    let reason = err.lines().last().unwrap_or_default();
+                    anyhow!(
+                        "Invalid regex at column {}: {}",
+                        column(input, start),
+                        reason.trim_start_matches("error: ")
+                    )
+                })?))
+            }
+            _ => word(input, &mut chars)?,
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+/// Read a keyword or a search term that isnt quoted. Terms like `level:error` are filters on
+/// something other than the text, and their value can be quoted
+fn word(input: &str, chars: &mut Peekable<CharIndices>) -> Result<Token> {
+    let start = chars.peek().map_or(input.len(), |&(start, _)| start);
+    let mut word = String::new();
+    while let Some((_, c)) = chars.next_if(|&(_, c)| !c.is_whitespace() && !"()\"".contains(c)) {
+        word.push(c);
+    }
+    match word.as_str() {
+        "AND" => return Ok(Token::And),
+        "OR" => return Ok(Token::Or),
+        "NOT" => return Ok(Token::Not),
+        _ => {}
+    }
+
+    let Some((key, value)) = word.split_once(':') else {
+        return Ok(Token::Term(Query::Text(word)));
+    };
+    let key = key.to_lowercase();
+    if !["level", "file", "after", "before"].contains(&key.as_str()) {
+        return Ok(Token::Term(Query::Text(word)));
+    }
+    let value = if value.is_empty() && chars.peek().is_some_and(|&(_, c)| c == '"') {
+        quoted(input, chars)?
+    } else {
+        value.to_string()
+    };
+    if value.is_empty() {
+        bail!(
+            "Missing value for {key}: at column {}",
+            column(input, start)
+        );
+    }
+    let time = || {
+        TimeBound::parse(&value).with_context(|| {
+            format!(
+                "Invalid time '{value}' for {key}: at column {}",
+                column(input, start)
+            )
+        })
+    };
+    Ok(Token::Term(match key.as_str() {
+        "level" => Query::Level(value),
+        "file" => Query::File(value),
+        "after" => Query::After(time()?),
+        _ => Query::Before(time()?),
+    }))
+}
+
+/// Read a string in double quotes, a quote inside it is written as `\"`
+fn quoted(input: &str, chars: &mut Peekable<CharIndices>) -> Result<String> {
+    let start = chars.next().map_or(input.len(), |(start, _)| start);
+    delimited(chars, '"')
+        .with_context(|| format!("Unclosed quote at column {}", column(input, start)))
+}
+
+/// Read up to the next 'end' that isnt escaped with a backslash. Other escapes are kept as they
+/// are so that regexes can use them. Returns `None` if the input runs out first
+fn delimited(chars: &mut Peekable<CharIndices>, end: char) -> Option<String> {
+    let mut text = String::new();
+    loop {
+        match chars.next()?.1 {
+            '\\' if chars.peek().is_some_and(|&(_, c)| c == end) => {
+                text.push(chars.next()?.1);
+            }
+            c if c == end => return Some(text),
+            c => text.push(c),
+        }
+    }
+}
+
+/// Column of the byte offset 'position' in 'input', starting at 1
+fn column(input: &str, position: usize) -> usize {
+    input[..position].chars().count() + 1
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    text.get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::get_level;
+
+    use super::*;
+
+    fn entry(time: &str, level: &str, text: &str) -> Entry {
+        let timestamp = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap();
+        Entry::new(timestamp, get_level(level), text)
+    }
+
+    fn parse(input: &str) -> Query {
+        Query::parse(input).unwrap().unwrap()
+    }
+
+    fn error(input: &str) -> String {
+        Query::parse(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn example_query() {
+        let query =
+            parse(r#"level:error file:vrserver "lighthouse" AND NOT /timeout \d+/ after:10:15"#);
+        let matching = entry("2024-07-12 10:20:00", "Error", "lighthouse lost tracking");
+        assert!(query.matches("vrserver.txt", &matching));
+        assert!(!query.matches("vrmonitor.txt", &matching));
+        let timeout = entry("2024-07-12 10:20:00", "Error", "lighthouse timeout 30");
+        assert!(!query.matches("vrserver.txt", &timeout));
+        let early = entry("2024-07-12 10:10:00", "Error", "lighthouse lost tracking");
+        assert!(!query.matches("vrserver.txt", &early));
+        let info = entry("2024-07-12 10:20:00", "Info", "lighthouse lost tracking");
+        assert!(!query.matches("vrserver.txt", &info));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let query = parse("a b OR c");
+        let time = "2024-07-12 10:00:00";
+        assert!(query.matches("log", &entry(time, "Info", "a b")));
+        assert!(query.matches("log", &entry(time, "Info", "c")));
+        assert!(!query.matches("log", &entry(time, "Info", "a")));
+        let query = parse("a (b OR c)");
+        assert!(query.matches("log", &entry(time, "Info", "a c")));
+        assert!(!query.matches("log", &entry(time, "Info", "c")));
+    }
+
+    #[test]
+    fn blank_input_has_no_query() {
+        assert!(Query::parse("  ").unwrap().is_none());
+    }
+
+    #[test]
+    fn missing_terms_are_reported() {
+        assert_eq!(error("a OR"), "Expected a search term at the end");
+        assert_eq!(error("a AND OR b"), "Expected a search term at column 7");
+    }
+
+    #[test]
+    fn unbalanced_brackets_are_reported_with_their_column() {
+        assert_eq!(error("(a"), "Unclosed '(' at column 1");
+        assert_eq!(error("a)"), "Unexpected ')' at column 2");
+        assert_eq!(error("b (a"), "Unclosed '(' at column 3");
+    }
+
+    #[test]
+    fn quoted_values() {
+        let query = parse(r#"after:"2024-07-12 10:15""#);
+        assert!(query.matches("log", &entry("2024-07-12 10:15:00", "Info", "")));
+        assert!(!query.matches("log", &entry("2024-07-12 10:14:59", "Info", "")));
+        let query = parse(r#""say \"hi\"""#);
+        assert!(query.matches("log", &entry("2024-07-12 10:00:00", "Info", r#"say "hi""#)));
+        assert_eq!(error(r#"a "open"#), "Unclosed quote at column 3");
+    }
+
+    #[test]
+    fn invalid_terms_are_reported_with_their_column() {
+        assert!(error("a /(/").starts_with("Invalid regex at column 3: "));
+        assert_eq!(error("/abc"), "Unclosed regex at column 1");
+        assert_eq!(error("level:"), "Missing value for level: at column 1");
+        assert_eq!(
+            error("x before:soon"),
+            "Invalid time 'soon' for before: at column 3"
+        );
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        assert_eq!(error("é)"), "Unexpected ')' at column 2");
+    }
+
+    #[test]
+    fn time_bounds() {
+        assert!(matches!(
+            TimeBound::parse("10:15"),
+            Some(TimeBound::Time(_))
+        ));
+        assert!(matches!(
+            TimeBound::parse("10:15:30.5"),
+            Some(TimeBound::Time(_))
+        ));
+        assert!(matches!(
+            TimeBound::parse("2024-07-12"),
+            Some(TimeBound::Date(_))
+        ));
+        assert!(matches!(
+            TimeBound::parse("2024-07-12T10:15"),
+            Some(TimeBound::DateTime(_))
+        ));
+        assert!(matches!(
+            TimeBound::parse("2024-07-12 10:15:30"),
+            Some(TimeBound::DateTime(_))
+        ));
+        assert!(TimeBound::parse("10h").is_none());
+
+        let timestamp =
+            NaiveDateTime::parse_from_str("2024-07-12 10:15:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let bound = TimeBound::parse("10:20").unwrap();
+        assert_eq!(bound.compare(&timestamp), Ordering::Less);
+        let bound = TimeBound::parse("2024-07-12").unwrap();
+        assert_eq!(bound.compare(&timestamp), Ordering::Equal);
+    }
+
+    #[test]
+    fn highlights_leave_out_negated_terms() {
+        let mut ranges = Vec::new();
+        parse(r"tracking NOT lost /(\d+)ms/").highlights("lost tracking 20ms", &mut ranges);
+        assert_eq!(ranges, vec![5..13, 14..16]);
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    io::{self, ErrorKind, Read},
+    process::{Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use chrono::Local;
+
+use crate::{
+    encoding::Encoding,
+    format::{LogFormat, PlainText},
+    parser::{parse_bytes, sniff_sample},
+    types::{Log, SharedLog},
+};
+
+/// Size of the buffer used for each read from a stream
+const READ_SIZE: usize = 8192;
+
+/// Build an empty log for data that arrives while the program is running. If 'format' is `None`
+/// the log starts out as plain text until the format can be detected from the stream
+pub fn stream_log(name: &str, format: Option<&Arc<dyn LogFormat>>) -> SharedLog {
+    let now = Local::now().naive_local();
+    let format = format.map_or_else(
+        || Arc::new(PlainText::new(now)) as Arc<dyn LogFormat>,
+        |format| format.for_file(now).unwrap_or_else(|| format.clone()),
+    );
+    Arc::new(Mutex::new(Log::new(&name, format)))
+}
+
+/// Read 'reader' until it ends, parsing lines into 'log' as soon as they are complete. If 'detect'
+/// is set the format of the log is detected from the first complete lines. This blocks until the
+/// stream is closed so it should be run on its own thread
+pub fn read_stream<R: Read>(mut reader: R, log: &SharedLog, mut detect: bool) -> io::Result<()> {
+    // Bytes read from the stream that dont make up a complete line yet
+    let mut pending = Vec::new();
+    let mut buffer = vec![0; READ_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        pending.extend_from_slice(&buffer[..read]);
+
+        let mut log = log.lock().unwrap();
+        if log.encoding().is_none() {
+            log.set_encoding(Encoding::detect(&pending));
+        }
+        let complete = log
+            .encoding()
+            .unwrap_or(Encoding::Utf8)
+            .complete_lines_len(&pending);
+        if complete > 0 {
+            if detect {
+                if let Some(format) = sniff_sample(&pending[..complete]) {
+                    log.set_format(format);
+                }
+                detect = false;
+            }
+            let rest = pending.split_off(complete);
+            parse_bytes(&mut log, &pending);
+            pending = rest;
+        }
+        drop(log);
+    }
+
+    // Whatever is left is the last line, it just wasnt ended with a newline
+    if !pending.is_empty() {
+        let mut log = log.lock().unwrap();
+        if detect {
+            if let Some(format) = sniff_sample(&pending) {
+                log.set_format(format);
+            }
+        }
+        parse_bytes(&mut log, &pending);
+        drop(log);
+    }
+    Ok(())
+}
+
+/// Read stdin into 'log' until it is closed
+pub fn read_stdin(log: &SharedLog, detect: bool) {
+    if let Err(err) = read_stream(io::stdin().lock(), log, detect) {
+        log.lock()
+            .unwrap()
+            .add_file_diagnostic(format!("Failed to read stdin: {err}"));
+    }
+}
+
+/// Run 'command' with the system shell and read its output into 'log' until it exits. If the
+/// command cant be started or doesnt exit cleanly it's recorded as a problem with the log
+pub fn read_command(command: &str, log: &SharedLog, detect: bool) {
+    match run_command(command, log, detect) {
+        Ok(status) if status.success() => {}
+        Ok(status) => log
+            .lock()
+            .unwrap()
+            .add_file_diagnostic(format!("Command exited with {status}")),
+        Err(err) => log
+            .lock()
+            .unwrap()
+            .add_file_diagnostic(format!("Failed to run command: {err:#}")),
+    }
+}
+
+fn run_command(command: &str, log: &SharedLog, detect: bool) -> Result<ExitStatus> {
+    // The TUI is drawn on stderr, so the command isnt allowed to write to it
+    let mut child = shell(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(stdout) = child.stdout.take() {
+        read_stream(stdout, log, detect)?;
+    }
+    Ok(child.wait()?)
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
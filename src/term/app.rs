@@ -1,10 +1,14 @@
-use std::sync::Arc;
+use std::{
+    iter,
+    ops::Range,
+    sync::{Arc, LazyLock},
+};
 
+use chrono::Local;
 use fuzzy_matcher::skim::SkimMatcherV2;
-use once_cell::sync::Lazy;
 use ratatui::{
     layout::Rect,
-    style::Stylize,
+    style::{Style, Stylize},
     widgets::{Block, Borders, ListState, Paragraph},
 };
 use regex::Regex;
@@ -12,9 +16,13 @@ use tui_input::Input;
 
 use crate::{
     parser::get_levels,
+    query::{regex_ranges, text_ranges, Query},
+    time_range::TimeRange,
     types::{Entry, SharedLog},
 };
 
+static MATCHER: LazyLock<SkimMatcherV2> = LazyLock::new(|| SkimMatcherV2::default().smart_case());
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Dir {
     Left,
@@ -26,25 +34,59 @@ pub enum FilterMode {
     Exact,
     Fuzzy,
     Regex(Option<Regex>),
+    /// The parsed query, or the problem with it if it couldnt be parsed
+    Query(Result<Option<Query>, String>),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum InputMode {
     Normal,
     Text,
+    /// Typing a line number to go to
+    Line,
+    /// Typing the time range to show entries from
+    Time,
+    /// Typing what to find in the entries list
+    Find,
 }
 
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)] // They are independent flags, not a state machine
 pub struct App {
     pub should_quit: bool,
     logs: Vec<SharedLog>,
     pub list_state: ListState,
     cursor: Dir,
     pub level_filter_popup: Option<ListState>,
+    pub diagnostics_popup: Option<ListState>,
+    /// Notes about where the logs were looked for, shown along with the problems reading them
+    pub notes: Vec<String>,
+    /// Set while the paths given on the command line are still being searched for logs
+    pub searching: bool,
+    /// Whether the selected entry is shown in full in a popup
+    pub details_popup: bool,
+    /// Whether the key bindings are listed in a popup
+    pub help_popup: bool,
+    /// Set when the selected entry should be opened in an editor before the next draw
+    pub open_in_editor: bool,
     filter_list: Vec<Arc<str>>,
     pub filter_mode: FilterMode,
     pub input_mode: InputMode,
     pub input: Input,
+    pub line_input: Input,
+    /// Why the line number typed in couldnt be gone to
+    pub line_error: Option<String>,
+    /// Only entries within this range are shown
+    pub time_range: TimeRange,
+    pub time_input: Input,
+    /// Why the time range typed in couldnt be used
+    pub time_error: Option<String>,
+    /// Method used to match the find input, which moves the selection between matching entries
+    /// without hiding the rest
+    pub find_mode: FilterMode,
+    pub find_input: Input,
+    /// Number of entries shown either side of each entry that passes the filter
+    pub context: usize,
     pub filter_zone: Rect,
     pub left_zone: Rect,
     pub right_zone: Rect,
@@ -58,10 +100,24 @@ impl App {
             list_state: ListState::default().with_selected(Some(0)),
             cursor: Dir::Left,
             level_filter_popup: None,
+            diagnostics_popup: None,
+            notes: Vec::new(),
+            searching: false,
+            details_popup: false,
+            help_popup: false,
+            open_in_editor: false,
             filter_list: Vec::default(),
             filter_mode: FilterMode::Exact,
             input_mode: InputMode::Normal,
             input: Input::default(),
+            line_input: Input::default(),
+            line_error: None,
+            time_range: TimeRange::default(),
+            time_input: Input::default(),
+            time_error: None,
+            find_mode: FilterMode::Exact,
+            find_input: Input::default(),
+            context: 0,
             filter_zone: Rect::default(),
             left_zone: Rect::default(),
             right_zone: Rect::default(),
@@ -72,32 +128,76 @@ impl App {
         self.logs.clone()
     }
 
-    pub fn right(&mut self) {
+    /// Rows shown in the Files list. Each row is the index of a log and, for the rows under an
+    /// expanded log, the index of one of its sessions
+    pub fn file_rows(&self) -> Vec<(usize, Option<usize>)> {
+        self.logs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, log)| {
+                let log = log.lock().unwrap();
+                let sessions = if log.expanded() {
+                    log.sessions().len()
+                } else {
+                    0
+                };
+                iter::once((i, None)).chain((0..sessions).map(move |session| (i, Some(session))))
+            })
+            .collect()
+    }
+
+    /// The log and session for the row selected in the Files list
+    pub fn selected_row(&self) -> (usize, Option<usize>) {
+        let rows = self.file_rows();
+        rows.get(
+            self.list_state
+                .selected()
+                .unwrap_or(0)
+                .min(rows.len().saturating_sub(1)),
+        )
+        .copied()
+        .unwrap_or((0, None))
+    }
+
+    /// The log selected in the Files list, `None` while there are no logs to show yet
+    pub fn selected_log(&self) -> Option<SharedLog> {
+        self.logs.get(self.selected_row().0).cloned()
+    }
+
+    /// Add a log to the end of the Files list once it has been found
+    pub fn add_log(&mut self, log: SharedLog) {
+        self.logs.push(log);
+    }
+
+    /// Clear the new marker of the selected log, since it has been seen now
+    pub fn mark_seen(&self) {
+        if let Some(log) = self.selected_log() {
+            log.lock().unwrap().mark_seen();
+        }
+    }
+
+    pub const fn right(&mut self) {
         self.cursor = Dir::Right;
     }
 
-    pub fn left(&mut self) {
+    pub const fn left(&mut self) {
         self.cursor = Dir::Left;
     }
 
     pub fn up(&mut self) {
         if let Some(list_state) = &mut self.level_filter_popup {
             list_state.select_previous();
+        } else if let Some(list_state) = &mut self.diagnostics_popup {
+            list_state.select_previous();
         } else {
             match self.cursor {
                 Dir::Left => {
                     self.list_state.select_previous();
                 }
                 Dir::Right => {
-                    self.logs[self
-                        .list_state
-                        .selected()
-                        .unwrap_or(0)
-                        .min(self.logs.len().saturating_sub(1))]
-                    .lock()
-                    .unwrap()
-                    .list_state_mut()
-                    .select_previous();
+                    if let Some(log) = self.selected_log() {
+                        log.lock().unwrap().list_state_mut().select_previous();
+                    }
                 }
             }
         }
@@ -106,40 +206,47 @@ impl App {
     pub fn down(&mut self) {
         if let Some(list_state) = &mut self.level_filter_popup {
             list_state.select_next();
+        } else if let Some(list_state) = &mut self.diagnostics_popup {
+            list_state.select_next();
         } else {
             match self.cursor {
                 Dir::Left => {
                     self.list_state.select_next();
                 }
                 Dir::Right => {
-                    self.logs[self
-                        .list_state
-                        .selected()
-                        .unwrap_or(0)
-                        .min(self.logs.len().saturating_sub(1))]
-                    .lock()
-                    .unwrap()
-                    .list_state_mut()
-                    .select_next();
+                    if let Some(log) = self.selected_log() {
+                        log.lock().unwrap().list_state_mut().select_next();
+                    }
                 }
             }
         }
     }
 
     pub fn enter(&mut self) {
-        if self.level_filter_popup.is_some() {
-            let selected = self
-                .level_filter_popup
-                .as_ref()
-                .unwrap()
-                .selected()
-                .unwrap();
+        if let Some(list_state) = &self.level_filter_popup {
+            let selected = list_state.selected().unwrap();
             let level = get_levels()[selected].clone();
             if self.filter_list.contains(&level) {
                 self.filter_list.retain(|entry| entry.ne(&level));
             } else {
                 self.filter_list.push(level);
             }
+        } else if self.diagnostics_popup.is_some() {
+        } else if self.cursor == Dir::Right {
+            self.details_popup = !self.details_popup;
+        } else {
+            // Show or hide the sessions of the selected log, selecting the log itself so the
+            // selection doesnt end up on a row that has just been hidden
+            let (selected, _) = self.selected_row();
+            let Some(log) = self.logs.get(selected) else {
+                return;
+            };
+            log.lock().unwrap().toggle_expanded();
+            let row = self
+                .file_rows()
+                .iter()
+                .position(|row| *row == (selected, None));
+            self.list_state.select(row);
         }
     }
 
@@ -149,15 +256,9 @@ impl App {
                 self.list_state.select_first();
             }
             Dir::Right => {
-                self.logs[self
-                    .list_state
-                    .selected()
-                    .unwrap_or(0)
-                    .min(self.logs.len().saturating_sub(1))]
-                .lock()
-                .unwrap()
-                .list_state_mut()
-                .select_first();
+                if let Some(log) = self.selected_log() {
+                    log.lock().unwrap().list_state_mut().select_first();
+                }
             }
         }
     }
@@ -168,15 +269,58 @@ impl App {
                 self.list_state.select_last();
             }
             Dir::Right => {
-                self.logs[self
-                    .list_state
-                    .selected()
-                    .unwrap_or(0)
-                    .min(self.logs.len().saturating_sub(1))]
-                .lock()
-                .unwrap()
-                .list_state_mut()
-                .select_last();
+                if let Some(log) = self.selected_log() {
+                    log.lock().unwrap().list_state_mut().select_last();
+                }
+            }
+        }
+    }
+
+    /// Select the entry at the line number typed into the line input, within the selected log
+    /// and session. Returns false if the input isnt a line number, or with the reason stored in
+    /// `line_error` if there are no entries at or after that line
+    pub fn go_to_line(&mut self) -> bool {
+        let Ok(line) = self.line_input.value().trim().parse::<usize>() else {
+            return false;
+        };
+        let (_, session) = self.selected_row();
+        let found = self.selected_log().is_some_and(|log| {
+            let mut log = log.lock().unwrap();
+            let range = session.map_or_else(
+                || 0..log.entries().len(),
+                |session| log.session_range(session),
+            );
+            log.select_line(line, range)
+        });
+        if !found {
+            self.line_error = Some("No entries at or after it".to_string());
+            return false;
+        }
+        self.line_error = None;
+        self.cursor = Dir::Right;
+        true
+    }
+
+    /// Set the time range from the time input. Returns false, with the reason stored in
+    /// `time_error`, if it isnt a valid range
+    pub fn apply_time_range(&mut self) -> bool {
+        let selected = self.selected_log().and_then(|log| {
+            let log = log.lock().unwrap();
+            log.selected_entry().map(|entry| *entry.timestamp())
+        });
+        match TimeRange::parse(
+            self.time_input.value(),
+            Local::now().naive_local(),
+            selected,
+        ) {
+            Ok(range) => {
+                self.time_range = range;
+                self.time_error = None;
+                true
+            }
+            Err(err) => {
+                self.time_error = Some(format!("{err:#}"));
+                false
             }
         }
     }
@@ -185,57 +329,271 @@ impl App {
         self.cursor
     }
 
-    pub fn filter_list_mut(&mut self) -> &mut Vec<Arc<str>> {
+    pub const fn filter_list_mut(&mut self) -> &mut Vec<Arc<str>> {
         &mut self.filter_list
     }
 
-    pub fn filter(&self, entry: &Entry) -> bool {
-        static MATCHER: Lazy<SkimMatcherV2> = Lazy::new(|| SkimMatcherV2::default().smart_case());
+    /// Returns true if 'entry', from the log with the given name, passes the level and text filters
+    pub fn filter(&self, log_name: &str, entry: &Entry) -> bool {
         !self.filter_list.contains(entry.log_level())
-            && (self.input.value().is_empty()
-                || match &self.filter_mode {
-                    FilterMode::Exact => entry.log_data().contains(self.input.value()),
-                    FilterMode::Fuzzy => MATCHER
-                        .fuzzy(entry.log_data(), self.input.value(), true)
-                        .is_some(),
-                    FilterMode::Regex(re) => {
-                        re.as_ref().map_or(true, |re| re.is_match(entry.log_data()))
-                    }
-                })
+            && self.time_range.contains(entry.timestamp())
+            && self
+                .filter_mode
+                .matches(self.input.value(), log_name, entry)
+    }
+
+    /// Returns true if something is being searched for with find
+    pub fn finding(&self) -> bool {
+        !self.find_input.value().is_empty() && self.find_mode.is_valid()
+    }
+
+    /// Returns true if 'entry', from the log with the given name, matches what is being searched
+    /// for with find
+    pub fn find_matches(&self, log_name: &str, entry: &Entry) -> bool {
+        self.finding()
+            && self
+                .find_mode
+                .matches(self.find_input.value(), log_name, entry)
     }
 
-    pub fn filter_widget(&self) -> Paragraph {
-        let title = match self.filter_mode {
-            FilterMode::Exact => "Filter - Exact",
-            FilterMode::Fuzzy => "Filter - Fuzzy",
-            FilterMode::Regex(_) => "Filter - Regex",
+    /// Select the next entry shown in the entries list that matches the find input, or the
+    /// previous one if 'forward' isnt set. Wraps around at either end of the list
+    pub fn find_next(&mut self, forward: bool) {
+        // Needs to be worked out before locking the log since it locks the logs itself
+        let (_, session) = self.selected_row();
+        let Some(log) = self.selected_log() else {
+            return;
         };
+        let mut log = log.lock().unwrap();
+        let matches = log
+            .shown_entries(self, session)
+            .into_iter()
+            .filter(|&(i, _)| self.find_matches(log.name(), &log.entries()[i]))
+            .map(|(i, _)| log.dropped() + i)
+            .collect::<Vec<_>>();
+        let current = log.selected_number();
+        let next = if forward {
+            matches
+                .iter()
+                .find(|&&number| current.is_none_or(|current| number > current))
+                .or_else(|| matches.first())
+        } else {
+            matches
+                .iter()
+                .rev()
+                .find(|&&number| current.is_none_or(|current| number < current))
+                .or_else(|| matches.last())
+        };
+        if let Some(&number) = next {
+            log.jump_to_entry(number);
+        }
+        drop(log);
+        self.cursor = Dir::Right;
+    }
 
-        let block = Block::default().borders(Borders::all()).title(title);
-        let block = match &self.filter_mode {
-            FilterMode::Regex(val) => {
-                if val.is_some() {
-                    block.green()
-                } else {
-                    block.red()
-                }
+    /// Byte ranges of the text of 'entry' that matched the text filter or find, sorted and with
+    /// overlapping ranges merged
+    pub fn highlights(&self, entry: &Entry) -> Vec<Range<usize>> {
+        let text = entry.log_data();
+        let mut ranges = Vec::new();
+        self.filter_mode
+            .highlights(self.input.value(), &text, &mut ranges);
+        if self.finding() {
+            self.find_mode
+                .highlights(self.find_input.value(), &text, &mut ranges);
+        }
+
+        ranges.sort_unstable_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
             }
-            _ => block,
-        };
+        }
+        merged
+    }
+
+    pub fn filter_widget(&self) -> Paragraph<'_> {
+        let mut title = format!("Filter - {}", self.filter_mode.name());
+        if !self.time_range.is_empty() {
+            title = format!("{title} - Time {}", self.time_range);
+        }
+        if self.context > 0 {
+            title = format!("{title} - Context {}", self.context);
+        }
+        let block = self
+            .filter_mode
+            .style_block(Block::default().borders(Borders::all()).title(title));
         let block = match &self.input_mode {
-            InputMode::Normal => block.dim(),
+            InputMode::Normal | InputMode::Line | InputMode::Time | InputMode::Find => block.dim(),
             InputMode::Text => block.bold(),
         };
         Paragraph::new(self.input.value()).block(block)
     }
 
-    pub fn update_regex(&mut self) {
-        if matches!(self.filter_mode, FilterMode::Regex(_)) {
-            if self.input.value().is_empty() {
-                self.filter_mode = FilterMode::Regex(None);
-            } else {
-                self.filter_mode = FilterMode::Regex(Regex::new(self.input.value()).ok());
+    /// Input box for the find popup
+    pub fn find_widget(&self) -> Paragraph<'_> {
+        let block = Block::default()
+            .borders(Borders::all())
+            .title(format!("Find - {}", self.find_mode.name()))
+            .title_style(Style::new().bold());
+        Paragraph::new(self.find_input.value()).block(self.find_mode.style_block(block))
+    }
+
+    /// Rebuild the regex or query from the filter input, if one is being used
+    pub fn update_filter(&mut self) {
+        self.filter_mode.update(self.input.value());
+    }
+
+    /// Rebuild the regex or query from the find input, if one is being used
+    pub fn update_find(&mut self) {
+        self.find_mode.update(self.find_input.value());
+    }
+}
+
+impl FilterMode {
+    /// The method after this one, in the order TAB goes through them
+    pub const fn next(&self) -> Self {
+        match self {
+            Self::Exact => Self::Fuzzy,
+            Self::Fuzzy => Self::Regex(None),
+            Self::Regex(_) => Self::Query(Ok(None)),
+            Self::Query(_) => Self::Exact,
+        }
+    }
+
+    pub const fn previous(&self) -> Self {
+        match self {
+            Self::Exact => Self::Query(Ok(None)),
+            Self::Fuzzy => Self::Exact,
+            Self::Regex(_) => Self::Fuzzy,
+            Self::Query(_) => Self::Regex(None),
+        }
+    }
+
+    /// Name shown in the title of the input box, along with the problem with the query if there
+    /// is one
+    fn name(&self) -> String {
+        match self {
+            Self::Exact => "Exact".to_string(),
+            Self::Fuzzy => "Fuzzy".to_string(),
+            Self::Regex(_) => "Regex".to_string(),
+            Self::Query(Err(err)) => format!("Query - {err}"),
+            Self::Query(Ok(_)) => "Query".to_string(),
+        }
+    }
+
+    /// Returns false if the input couldnt be turned into a regex or query
+    const fn is_valid(&self) -> bool {
+        match self {
+            Self::Exact | Self::Fuzzy => true,
+            Self::Regex(re) => re.is_some(),
+            Self::Query(query) => query.is_ok(),
+        }
+    }
+
+    /// Colour the block of an input box green or red for regexes and queries, depending on
+    /// whether the input is valid
+    fn style_block(&self, block: Block<'static>) -> Block<'static> {
+        match self {
+            Self::Exact | Self::Fuzzy => block,
+            _ if self.is_valid() => block.green(),
+            _ => block.red(),
+        }
+    }
+
+    /// Rebuild the regex or query from 'input', if this method uses one
+    fn update(&mut self, input: &str) {
+        match self {
+            Self::Regex(_) => {
+                *self = Self::Regex(if input.is_empty() {
+                    None
+                } else {
+                    Regex::new(input).ok()
+                });
             }
+            Self::Query(_) => {
+                *self = Self::Query(Query::parse(input).map_err(|err| format!("{err:#}")));
+            }
+            Self::Exact | Self::Fuzzy => {}
         }
     }
+
+    /// Returns true if 'entry', from the log with the given name, matches 'input'. Empty input
+    /// and input that isnt valid match everything
+    fn matches(&self, input: &str, log_name: &str, entry: &Entry) -> bool {
+        input.is_empty()
+            || match self {
+                Self::Exact => entry.log_data().contains(input),
+                Self::Fuzzy => MATCHER.fuzzy(&entry.log_data(), input, true).is_some(),
+                Self::Regex(re) => re.as_ref().is_none_or(|re| re.is_match(&entry.log_data())),
+                Self::Query(query) => query.as_ref().map_or(true, |query| {
+                    query
+                        .as_ref()
+                        .is_none_or(|query| query.matches(log_name, entry))
+                }),
+            }
+    }
+
+    /// Add the byte ranges of 'text' that match 'input' to 'ranges'
+    fn highlights(&self, input: &str, text: &str, ranges: &mut Vec<Range<usize>>) {
+        if input.is_empty() {
+            return;
+        }
+        match self {
+            Self::Exact => ranges.extend(text_ranges(text, input)),
+            Self::Fuzzy => {
+                // The matcher gives the index of each matched character rather than bytes
+                if let Some((_, indices)) = MATCHER.fuzzy(text, input, true) {
+                    let mut indices = indices.into_iter().peekable();
+                    for (i, (start, c)) in text.char_indices().enumerate() {
+                        if indices.next_if_eq(&i).is_some() {
+                            ranges.push(start..start + c.len_utf8());
+                        }
+                    }
+                }
+            }
+            Self::Regex(re) => {
+                if let Some(re) = re {
+                    ranges.extend(regex_ranges(re, text));
+                }
+            }
+            Self::Query(query) => {
+                if let Ok(Some(query)) = query {
+                    query.highlights(text, ranges);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlights(mode: &FilterMode, input: &str, text: &str) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        mode.highlights(input, text, &mut ranges);
+        ranges
+    }
+
+    #[test]
+    fn fuzzy_matches_are_highlighted_by_byte() {
+        assert_eq!(
+            highlights(&FilterMode::Fuzzy, "\u{f6}b", "f\u{f6}o bar"),
+            [1..3, 5..6]
+        );
+        assert!(highlights(&FilterMode::Fuzzy, "xyz", "f\u{f6}o bar").is_empty());
+    }
+
+    #[test]
+    fn exact_and_regex_matches_are_highlighted() {
+        assert_eq!(
+            highlights(&FilterMode::Exact, "o", "f\u{f6}o boo"),
+            [3..4, 6..7, 7..8]
+        );
+        let regex = FilterMode::Regex(Regex::new("b.r").ok());
+        assert_eq!(highlights(&regex, "b.r", "bar\nbur"), [0..3, 4..7]);
+    }
 }
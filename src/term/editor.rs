@@ -0,0 +1,65 @@
+use std::{env::var, path::Path, process::Command};
+
+use anyhow::{Context, Result};
+
+use super::{app::App, tui::Tui};
+
+/// Used when neither `EDITOR` nor `PAGER` is set
+#[cfg(not(windows))]
+const DEFAULT_VIEWER: Option<&str> = Some("less");
+#[cfg(windows)]
+const DEFAULT_VIEWER: Option<&str> = None;
+
+/// Open the file the selected entry was read from at the line it starts on, using `EDITOR` or
+/// `PAGER`. The TUI is suspended until the program exits. Problems with the viewer are recorded
+/// against the log, only problems with the terminal itself are returned
+pub fn open_selected(tui: &mut Tui, app: &App) -> Result<()> {
+    let Some(log) = app.selected_log() else {
+        return Ok(());
+    };
+    let location = log
+        .lock()
+        .unwrap()
+        .selected_location()
+        .map(|(path, line)| (path.to_path_buf(), line));
+    let command = location
+        .context("The selected entry wasnt read from a file that can be opened")
+        .and_then(|(path, line)| viewer_command(&path, line));
+    let problem = match command {
+        Ok(mut command) => match tui.suspend(|| command.status())? {
+            Ok(status) if status.success() => None,
+            Ok(status) => Some(format!("Viewer exited with {status}")),
+            Err(err) => Some(format!("Failed to run viewer: {err}")),
+        },
+        Err(err) => Some(format!("Failed to open in viewer: {err:#}")),
+    };
+    if let Some(problem) = problem {
+        log.lock().unwrap().add_file_diagnostic(problem);
+    }
+    Ok(())
+}
+
+/// Command that opens 'path' at 'line'. The program may be given with arguments, like `nano -v`.
+/// Most editors and pagers, including vi, nano, emacs and less, accept the line as `+LINE`, VS Code
+/// and its forks take `-g PATH:LINE` instead
+fn viewer_command(path: &Path, line: usize) -> Result<Command> {
+    let program = ["EDITOR", "PAGER"]
+        .into_iter()
+        .filter_map(|name| var(name).ok())
+        .find(|value| !value.trim().is_empty())
+        .or_else(|| DEFAULT_VIEWER.map(str::to_string))
+        .context("Set EDITOR or PAGER to open logs")?;
+    let mut words = program.split_whitespace();
+    let program = words.next().unwrap();
+    let mut command = Command::new(program);
+    command.args(words);
+    let name = Path::new(program)
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_lowercase());
+    if name.is_some_and(|name| ["code", "code-insiders", "codium"].contains(&name.as_str())) {
+        command.arg("-g").arg(format!("{}:{line}", path.display()));
+    } else {
+        command.arg(format!("+{line}")).arg(path);
+    }
+    Ok(command)
+}
@@ -1,5 +1,8 @@
 use std::{
-    sync::mpsc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
     thread,
     time::{Duration, Instant},
 };
@@ -17,6 +20,7 @@ pub enum Event {
     /// Mouse click/scroll.
     Mouse(MouseEvent),
     /// Terminal resize.
+    #[allow(dead_code)]
     Resize(u16, u16),
     // Gain Focus Event, needed for windows compatibility.
     FocusGained,
@@ -36,18 +40,28 @@ pub struct EventHandler {
     /// Event handler thread.
     #[allow(dead_code)]
     handler: thread::JoinHandle<()>,
+    /// Set while another program is using the terminal, so that its input isnt read.
+    paused: Arc<AtomicBool>,
+    tick_rate: Duration,
 }
 
 impl EventHandler {
     pub fn new(tick_rate: u64) -> Self {
         let tick_rate = Duration::from_millis(tick_rate);
         let (sender, receiver) = mpsc::channel();
+        let paused = Arc::new(AtomicBool::new(false));
         let handler = {
             let sender = sender.clone();
+            let paused = paused.clone();
             thread::spawn(move || {
                 let mut last_tick = Instant::now();
                 let mut live = true;
                 while live {
+                    if paused.load(Ordering::Relaxed) {
+                        thread::sleep(tick_rate);
+                        continue;
+                    }
+
                     let timeout = tick_rate
                         .checked_sub(last_tick.elapsed())
                         .unwrap_or(tick_rate);
@@ -83,9 +97,22 @@ impl EventHandler {
             sender,
             receiver,
             handler,
+            paused,
+            tick_rate,
         }
     }
 
+    /// Stop reading terminal events until `resume` is called. Waits long enough for a poll that
+    /// was already in progress to finish
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+        thread::sleep(self.tick_rate * 2);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
     pub fn next(&self) -> Result<Event> {
         Ok(self.receiver.recv()?)
     }
@@ -3,3 +3,4 @@ pub mod tui;
 pub mod ui;
 pub mod app;
 pub mod update;
+pub mod editor;
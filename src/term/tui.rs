@@ -19,13 +19,6 @@ pub struct Tui {
 
 impl Tui {
     pub fn new(terminal: CrosstermTerminal, events: EventHandler) -> Self {
-        Self { terminal, events }
-    }
-
-    pub fn enter(&mut self) -> Result<()> {
-        terminal::enable_raw_mode()?;
-        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
-
         // Define custom panic hook, so that we can reset the terminal state in case of a panic
         // so that the terminal doesnt end up messed up if we panic and close the application
         let panic_hook = panic::take_hook();
@@ -34,6 +27,13 @@ impl Tui {
             panic_hook(panic);
         }));
 
+        Self { terminal, events }
+    }
+
+    pub fn enter(&mut self) -> Result<()> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+
         self.terminal.hide_cursor()?;
         self.terminal.clear()?;
 
@@ -54,6 +54,17 @@ impl Tui {
         Ok(())
     }
 
+    /// Give the terminal back while 'run' runs another program in it, then take it over again.
+    /// The screen is cleared when coming back so everything gets redrawn
+    pub fn suspend<T>(&mut self, run: impl FnOnce() -> T) -> Result<T> {
+        self.events.pause();
+        self.exit()?;
+        let result = run();
+        self.enter()?;
+        self.events.resume();
+        Ok(result)
+    }
+
     pub fn draw(&mut self, app: &mut App) -> Result<()> {
         self.terminal.draw(|frame| ui::render(app, frame))?;
         Ok(())
@@ -4,8 +4,11 @@ use itertools::Itertools;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Style, Stylize},
-    text::ToLine,
-    widgets::{Block, Borders, Clear, List, Paragraph, Scrollbar, ScrollbarState, Wrap},
+    text::{Line, Span, ToLine},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, Scrollbar, ScrollbarState,
+        Wrap,
+    },
     Frame,
 };
 
@@ -46,12 +49,17 @@ pub fn render_log_files_list(app: &mut App, f: &mut Frame, layouts: &Layouts) {
         super::app::Dir::Left => Style::new().reversed(),
         super::app::Dir::Right => Style::new().reversed().dim(),
     };
+    let rows = app.file_rows();
     let arcs = app.logs();
     let log_files = arcs.iter().map(|file| file.lock().unwrap()).collect_vec();
     let list = List::new(
-        log_files
-            .iter()
-            .map(|file| file.as_list_item())
+        rows.iter()
+            .map(|(log, session)| {
+                session.map_or_else(
+                    || log_files[*log].as_list_item(),
+                    |session| log_files[*log].session_list_item(session),
+                )
+            })
             .collect_vec(),
     )
     .block(
@@ -65,7 +73,7 @@ pub fn render_log_files_list(app: &mut App, f: &mut Frame, layouts: &Layouts) {
 
     // Render associated scroll bar
     let mut state =
-        ScrollbarState::new(app.logs().len()).position(app.list_state.selected().unwrap());
+        ScrollbarState::new(rows.len()).position(app.list_state.selected().unwrap_or(0));
     f.render_stateful_widget(
         Scrollbar::default().orientation(ratatui::widgets::ScrollbarOrientation::VerticalRight),
         layouts.lower[0],
@@ -74,19 +82,35 @@ pub fn render_log_files_list(app: &mut App, f: &mut Frame, layouts: &Layouts) {
 }
 
 pub fn render_log_entries(app: &App, f: &mut Frame, layouts: &Layouts) {
+    // Needs to be worked out before locking the logs since it locks them itself
+    let (selected, session) = app.selected_row();
     let arcs = app.logs();
+    if arcs.is_empty() {
+        let block = Block::new()
+            .borders(Borders::all())
+            .title("Log")
+            .title_style(Style::new().bold());
+        let status = if app.searching {
+            "Searching for log files"
+        } else {
+            "No log files found"
+        };
+        let text = std::iter::once(status)
+            .chain(app.notes.iter().map(String::as_str))
+            .join("\n");
+        f.render_widget(Paragraph::new(text).block(block), layouts.lower[1]);
+        return;
+    }
     let mut log_files = arcs.iter().map(|file| file.lock().unwrap()).collect_vec();
 
-    // Render log file entries
-    let selected = app
-        .list_state
-        .selected()
-        .unwrap_or(0)
-        .min(log_files.len() - 1);
-    let mut list_state = log_files[selected].list_state_mut().to_owned();
-    let list = log_files[selected].get_list(app);
-    f.render_stateful_widget(list, layouts.lower[1], &mut list_state);
-    *log_files[selected].list_state_mut() = list_state;
+    // Render log file entries, only those from the selected session if there is one. The list
+    // only holds the rows from the scroll offset on, so the selection is drawn relative to that
+    let height = usize::from(layouts.lower[1].height.saturating_sub(2));
+    let (list, rows, list_state) = log_files[selected].get_list(app, session, height);
+    let mut shown_state = ListState::default()
+        .with_selected(list_state.selected().map(|row| row - list_state.offset()));
+    f.render_stateful_widget(list, layouts.lower[1], &mut shown_state);
+    log_files[selected].set_list_state(list_state, &rows);
 
     // Render associated scroll bar
     let mut state = ScrollbarState::new(log_files[selected].entries().len())
@@ -117,10 +141,10 @@ pub fn render(app: &mut App, f: &mut Frame) {
         );
     }
 
-    // Help text
-    let help_text = Paragraph::new(
-        "HOME move to top. END move to bottom. RIGHT/LEFT select between log and file menus. CTRL-F to search. SHIFT-F filter by log level. TAB in filer search change method"
-        ).wrap(Wrap{ trim: true }).bold();
+    // Help text, the rest of the keys are listed in the help popup
+    let help_text = Paragraph::new("? show all keys. CTRL-F filter. / find. Q quit")
+        .wrap(Wrap { trim: true })
+        .bold();
     f.render_widget(help_text, layouts.upper[0]);
 
     render_log_files_list(app, f, &layouts);
@@ -135,28 +159,7 @@ pub fn render(app: &mut App, f: &mut Frame) {
 
     // Log level filter popup
     if app.level_filter_popup.is_some() {
-        let size_x = 30;
-        let size_y = 8;
-        let spare_x = f.size().width.saturating_sub(size_x);
-        let spare_y = f.size().height.saturating_sub(size_y);
-        let vertical = Layout::new(
-            Direction::Vertical,
-            [
-                Constraint::Length(spare_y.saturating_div(2)),
-                Constraint::Min(size_y),
-                Constraint::Length(spare_y.saturating_div(2)),
-            ],
-        )
-        .split(f.size());
-        let horizontal = Layout::new(
-            Direction::Horizontal,
-            [
-                Constraint::Length(spare_x.saturating_div(2)),
-                Constraint::Min(size_x),
-                Constraint::Length(spare_x.saturating_div(2)),
-            ],
-        )
-        .split(vertical[1]);
+        let area = centered_rect(30, 8, f.size());
         let levels = get_levels();
         let items = levels
             .iter()
@@ -185,11 +188,232 @@ pub fn render(app: &mut App, f: &mut Frame) {
             )
             .highlight_symbol(">> ");
 
-        f.render_widget(Clear, horizontal[1]);
-        f.render_stateful_widget(
-            list,
-            horizontal[1],
-            app.level_filter_popup.as_mut().unwrap(),
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(list, area, app.level_filter_popup.as_mut().unwrap());
+    }
+
+    if app.diagnostics_popup.is_some() {
+        render_diagnostics(app, f);
+    }
+
+    if app.details_popup {
+        render_entry_details(app, f);
+    }
+
+    if app.input_mode == InputMode::Line {
+        render_line_input(app, f);
+    }
+
+    if app.input_mode == InputMode::Time {
+        render_time_input(app, f);
+    }
+
+    if app.input_mode == InputMode::Find {
+        render_find_input(app, f, &layouts);
+    }
+
+    if app.help_popup {
+        render_help(f);
+    }
+}
+
+/// Returns a rect of the given size centered inside 'area'
+fn centered_rect(size_x: u16, size_y: u16, area: Rect) -> Rect {
+    let spare_x = area.width.saturating_sub(size_x);
+    let spare_y = area.height.saturating_sub(size_y);
+    let vertical = Layout::new(
+        Direction::Vertical,
+        [
+            Constraint::Length(spare_y.saturating_div(2)),
+            Constraint::Min(size_y),
+            Constraint::Length(spare_y.saturating_div(2)),
+        ],
+    )
+    .split(area);
+    Layout::new(
+        Direction::Horizontal,
+        [
+            Constraint::Length(spare_x.saturating_div(2)),
+            Constraint::Min(size_x),
+            Constraint::Length(spare_x.saturating_div(2)),
+        ],
+    )
+    .split(vertical[1])[1]
+}
+
+/// Popup listing every problem found while reading the logs
+pub fn render_diagnostics(app: &mut App, f: &mut Frame) {
+    let area = centered_rect(
+        f.size().width.saturating_mul(4) / 5,
+        f.size().height.saturating_mul(3) / 5,
+        f.size(),
+    );
+    let arcs = app.logs();
+    let log_files = arcs.iter().map(|file| file.lock().unwrap()).collect_vec();
+    let items = log_files
+        .iter()
+        .flat_map(|log| {
+            log.diagnostics()
+                .iter()
+                .map(|diagnostic| diagnostic.as_list_item(log.name()))
+        })
+        .collect_vec();
+    let count = items.len();
+    // The notes come first so they can be seen without scrolling past the problems
+    let items = app
+        .notes
+        .iter()
+        .map(|note| ListItem::new(note.as_str()).gray())
+        .chain(items)
+        .collect_vec();
+    let block = Block::default()
+        .borders(Borders::all())
+        .title(format!("Problems ({count})"))
+        .title_style(Style::new().bold())
+        .title_alignment(ratatui::layout::Alignment::Center);
+
+    f.render_widget(Clear, area);
+    if items.is_empty() {
+        f.render_widget(
+            Paragraph::new("No problems found while reading the logs")
+                .centered()
+                .block(block.green()),
+            area,
         );
+    } else {
+        let block = if count == 0 {
+            block.green()
+        } else {
+            block.light_red()
+        };
+        let list = List::new(items).block(block).highlight_symbol(">> ");
+        f.render_stateful_widget(list, area, app.diagnostics_popup.as_mut().unwrap());
     }
 }
+
+/// Popup showing the whole of the selected entry along with where it came from
+pub fn render_entry_details(app: &App, f: &mut Frame) {
+    let area = centered_rect(
+        f.size().width.saturating_mul(4) / 5,
+        f.size().height.saturating_mul(3) / 5,
+        f.size(),
+    );
+    let block = Block::default()
+        .borders(Borders::all())
+        .title("Entry")
+        .title_style(Style::new().bold())
+        .title_alignment(ratatui::layout::Alignment::Center);
+
+    f.render_widget(Clear, area);
+    let Some(log) = app.selected_log() else {
+        f.render_widget(Paragraph::new("No entry selected").block(block), area);
+        return;
+    };
+    let log = log.lock().unwrap();
+    let paragraph = log.selected_entry().map_or_else(
+        || Paragraph::new("No entry selected"),
+        |entry| Paragraph::new(entry.detail_lines(&log.selected_source())),
+    );
+    f.render_widget(paragraph.wrap(Wrap { trim: false }).block(block), area);
+    drop(log);
+}
+
+/// Popup for typing the line number to go to
+#[allow(clippy::cast_possible_truncation)]
+pub fn render_line_input(app: &App, f: &mut Frame) {
+    let area = centered_rect(40, 3, f.size());
+    let valid = app.line_input.value().trim().parse::<usize>().is_ok();
+    let block = Block::default()
+        .borders(Borders::all())
+        .title_style(Style::new().bold());
+    let block = match &app.line_error {
+        Some(err) => block.title(format!("Go to line - {err}")).red(),
+        None if valid || app.line_input.value().is_empty() => block.title("Go to line").green(),
+        None => block.title("Go to line").red(),
+    };
+
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(app.line_input.value()).block(block), area);
+    f.set_cursor(area.x + 1 + app.line_input.cursor() as u16, area.y + 1);
+}
+
+/// Popup for typing the time range to show entries from
+#[allow(clippy::cast_possible_truncation)]
+pub fn render_time_input(app: &App, f: &mut Frame) {
+    let area = centered_rect(f.size().width.saturating_mul(3) / 5, 3, f.size());
+    let block = Block::default()
+        .borders(Borders::all())
+        .title_style(Style::new().bold());
+    let block = match &app.time_error {
+        Some(err) => block.title(format!("Time range - {err}")).red(),
+        None => block
+            .title("Time range - 10:15..10:20, last 10m, ±30s around selected")
+            .green(),
+    };
+
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(app.time_input.value()).block(block), area);
+    f.set_cursor(area.x + 1 + app.time_input.cursor() as u16, area.y + 1);
+}
+
+/// Input box for find, over the bottom of the entries list so the matches can still be seen
+#[allow(clippy::cast_possible_truncation)]
+pub fn render_find_input(app: &App, f: &mut Frame, layouts: &Layouts) {
+    let entries = layouts.lower[1];
+    let area = Rect {
+        y: entries.bottom().saturating_sub(3),
+        height: entries.height.min(3),
+        ..entries
+    };
+
+    f.render_widget(Clear, area);
+    f.render_widget(app.find_widget(), area);
+    f.set_cursor(area.x + 1 + app.find_input.cursor() as u16, area.y + 1);
+}
+
+/// Key bindings listed in the help popup
+const KEYS: [(&str, &str); 16] = [
+    ("UP/DOWN", "move the selection"),
+    ("HOME/END", "move to the top or bottom"),
+    ("LEFT/RIGHT", "select between the file and log lists"),
+    (
+        "ENTER",
+        "show the sessions of a file or the details of an entry",
+    ),
+    ("CTRL-F", "type a filter, TAB changes the filter method"),
+    ("SHIFT-F", "filter by log level"),
+    ("T", "set the time range"),
+    ("+/-", "change the context shown around filter matches"),
+    ("/", "find, TAB changes the find method"),
+    ("N/SHIFT-N", "next or previous match"),
+    ("G", "go to line"),
+    ("E", "open the entry in $EDITOR"),
+    ("SHIFT-D", "show problems reading the logs"),
+    ("?", "show or hide this help"),
+    ("ESC/Q", "close the popup, or quit"),
+    ("CTRL-C", "quit"),
+];
+
+/// Popup listing every key binding
+#[allow(clippy::cast_possible_truncation)]
+pub fn render_help(f: &mut Frame) {
+    let area = centered_rect(70, KEYS.len() as u16 + 2, f.size());
+    let block = Block::default()
+        .borders(Borders::all())
+        .title("Keys")
+        .title_style(Style::new().bold())
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .green();
+    let lines = KEYS
+        .iter()
+        .map(|(key, action)| {
+            Line::from(vec![
+                Span::from(format!("{key:>11}  ")).bold(),
+                Span::from(*action),
+            ])
+        })
+        .collect_vec();
+
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
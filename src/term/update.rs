@@ -2,107 +2,184 @@ use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, Mous
 use ratatui::{layout::Position, widgets::ListState};
 use tui_input::backend::crossterm::EventHandler;
 
-use super::app::{App, FilterMode, InputMode};
+use super::app::{App, InputMode};
 
 pub fn handle_keys(app: &mut App, key_event: KeyEvent) {
     match app.input_mode {
-        super::app::InputMode::Normal => match key_event.code {
-            KeyCode::Esc | KeyCode::Char('q' | 'Q') => {
-                if app.level_filter_popup.is_some() {
-                    app.level_filter_popup = None;
-                } else {
-                    app.should_quit = true;
-                }
+        super::app::InputMode::Normal => handle_normal_keys(app, key_event),
+        super::app::InputMode::Find => match key_event.code {
+            KeyCode::Esc => {
+                app.find_input.reset();
+                app.update_find();
+                app.input_mode = InputMode::Normal;
             }
-            KeyCode::Char('c' | 'C') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    app.should_quit = true;
-                }
+            KeyCode::Enter => {
+                app.input_mode = InputMode::Normal;
+                app.find_next(true);
             }
-            KeyCode::Up => app.up(),
-            KeyCode::Down => app.down(),
-            KeyCode::Left => app.left(),
-            KeyCode::Right => app.right(),
-            KeyCode::Home => app.home(),
-            KeyCode::End => app.end(),
-            KeyCode::Enter | KeyCode::Char(' ') => app.enter(),
-            KeyCode::Char('f') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    app.input_mode = InputMode::Text;
-                }
+            KeyCode::Tab => {
+                app.find_mode = app.find_mode.next();
+                app.update_find();
             }
-            KeyCode::Char('F') => {
-                if app.level_filter_popup.is_some() {
-                    app.level_filter_popup = None;
-                } else {
-                    app.level_filter_popup = Some(ListState::default().with_selected(Some(0)));
-                }
+            _ => {
+                app.find_input.handle_event(&Event::Key(key_event));
+                app.update_find();
             }
-            _ => {}
         },
         super::app::InputMode::Text => match key_event.code {
             KeyCode::Esc => {
                 app.input_mode = InputMode::Normal;
             }
             KeyCode::Tab => {
-                match app.filter_mode {
-                    FilterMode::Exact => app.filter_mode = FilterMode::Fuzzy,
-                    FilterMode::Fuzzy => app.filter_mode = FilterMode::Regex(None),
-                    FilterMode::Regex(_) => app.filter_mode = FilterMode::Exact,
-                }
-                app.update_regex();
+                app.filter_mode = app.filter_mode.next();
+                app.update_filter();
             }
             _ => {
                 app.input.handle_event(&Event::Key(key_event));
-                app.update_regex();
+                app.update_filter();
+            }
+        },
+        super::app::InputMode::Line => match key_event.code {
+            KeyCode::Esc => {
+                app.input_mode = InputMode::Normal;
+            }
+            KeyCode::Enter => {
+                if app.go_to_line() {
+                    app.input_mode = InputMode::Normal;
+                }
+            }
+            _ => {
+                app.line_input.handle_event(&Event::Key(key_event));
+                app.line_error = None;
+            }
+        },
+        super::app::InputMode::Time => match key_event.code {
+            KeyCode::Esc => {
+                app.input_mode = InputMode::Normal;
+            }
+            KeyCode::Enter => {
+                if app.apply_time_range() {
+                    app.input_mode = InputMode::Normal;
+                }
+            }
+            _ => {
+                app.time_input.handle_event(&Event::Key(key_event));
+                app.time_error = None;
             }
         },
     }
 }
 
+fn handle_normal_keys(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q' | 'Q') => {
+            if app.help_popup {
+                app.help_popup = false;
+            } else if app.level_filter_popup.is_some() {
+                app.level_filter_popup = None;
+            } else if app.diagnostics_popup.is_some() {
+                app.diagnostics_popup = None;
+            } else if app.details_popup {
+                app.details_popup = false;
+            } else {
+                app.should_quit = true;
+            }
+        }
+        KeyCode::Char('c' | 'C') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.should_quit = true;
+        }
+        KeyCode::Up => app.up(),
+        KeyCode::Down => app.down(),
+        KeyCode::Left => app.left(),
+        KeyCode::Right => app.right(),
+        KeyCode::Home => app.home(),
+        KeyCode::End => app.end(),
+        KeyCode::Enter | KeyCode::Char(' ') => app.enter(),
+        KeyCode::Char('f') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.input_mode = InputMode::Text;
+        }
+        KeyCode::Char('F') => {
+            if app.level_filter_popup.is_some() {
+                app.level_filter_popup = None;
+            } else {
+                app.diagnostics_popup = None;
+                app.level_filter_popup = Some(ListState::default().with_selected(Some(0)));
+            }
+        }
+        KeyCode::Char('g' | 'G') => {
+            app.details_popup = false;
+            app.line_input.reset();
+            app.line_error = None;
+            app.input_mode = InputMode::Line;
+        }
+        KeyCode::Char('e' | 'E') => app.open_in_editor = true,
+        KeyCode::Char('/') => {
+            app.details_popup = false;
+            app.find_input.reset();
+            app.update_find();
+            app.input_mode = InputMode::Find;
+        }
+        KeyCode::Char('+' | '=') => app.context += 1,
+        KeyCode::Char('-') => app.context = app.context.saturating_sub(1),
+        KeyCode::Char('n') => app.find_next(true),
+        KeyCode::Char('N') => app.find_next(false),
+        KeyCode::Char('t' | 'T') => {
+            app.details_popup = false;
+            app.time_error = None;
+            app.input_mode = InputMode::Time;
+        }
+        KeyCode::Char('?') => app.help_popup = !app.help_popup,
+        KeyCode::Char('D') => {
+            if app.diagnostics_popup.is_some() {
+                app.diagnostics_popup = None;
+            } else {
+                app.level_filter_popup = None;
+                app.diagnostics_popup = Some(ListState::default().with_selected(Some(0)));
+            }
+        }
+        _ => {}
+    }
+}
+
 pub fn handle_mouse(app: &mut App, mouse_event: MouseEvent) {
     let position = Position {
         x: mouse_event.column,
         y: mouse_event.row,
     };
     match mouse_event.kind {
-        crossterm::event::MouseEventKind::Down(button) => {
-            if button == MouseButton::Left {
-                if app.left_zone.contains(position) {
-                    app.input_mode = InputMode::Normal;
-                    app.left();
-                } else if app.right_zone.contains(position) {
-                    app.input_mode = InputMode::Normal;
-                    app.right();
-                } else if app.filter_zone.contains(position) {
-                    app.input_mode = InputMode::Text;
-                }
+        crossterm::event::MouseEventKind::Down(MouseButton::Left) => {
+            if app.left_zone.contains(position) {
+                app.input_mode = InputMode::Normal;
+                app.left();
+            } else if app.right_zone.contains(position) {
+                app.input_mode = InputMode::Normal;
+                app.right();
+            } else if app.filter_zone.contains(position) {
+                app.input_mode = InputMode::Text;
             }
         }
-        crossterm::event::MouseEventKind::ScrollDown => {
-            if matches!(app.input_mode, InputMode::Normal) {
-                app.down();
-            } else {
-                match app.filter_mode {
-                    FilterMode::Exact => app.filter_mode = FilterMode::Fuzzy,
-                    FilterMode::Fuzzy => app.filter_mode = FilterMode::Regex(None),
-                    FilterMode::Regex(_) => app.filter_mode = FilterMode::Exact,
-                }
-                app.update_regex();
+        crossterm::event::MouseEventKind::ScrollDown => match app.input_mode {
+            InputMode::Normal | InputMode::Line | InputMode::Time => app.down(),
+            InputMode::Text => {
+                app.filter_mode = app.filter_mode.next();
+                app.update_filter();
             }
-        }
-        crossterm::event::MouseEventKind::ScrollUp => {
-            if matches!(app.input_mode, InputMode::Normal) {
-                app.up();
-            } else {
-                match app.filter_mode {
-                    FilterMode::Exact => app.filter_mode = FilterMode::Regex(None),
-                    FilterMode::Fuzzy => app.filter_mode = FilterMode::Exact,
-                    FilterMode::Regex(_) => app.filter_mode = FilterMode::Fuzzy,
-                }
-                app.update_regex();
+            InputMode::Find => {
+                app.find_mode = app.find_mode.next();
+                app.update_find();
             }
-        }
+        },
+        crossterm::event::MouseEventKind::ScrollUp => match app.input_mode {
+            InputMode::Normal | InputMode::Line | InputMode::Time => app.up(),
+            InputMode::Text => {
+                app.filter_mode = app.filter_mode.previous();
+                app.update_filter();
+            }
+            InputMode::Find => {
+                app.find_mode = app.find_mode.previous();
+                app.update_find();
+            }
+        },
         _ => {}
     }
 }
@@ -0,0 +1,241 @@
+use std::{cmp::Ordering, fmt::Display};
+
+use anyhow::{bail, Context, Result};
+use chrono::{NaiveDateTime, TimeDelta};
+
+use crate::query::TimeBound;
+
+/// Window of time that entries have to be in to be shown. Both ends are included
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeRange {
+    since: Option<TimeBound>,
+    until: Option<TimeBound>,
+}
+
+impl TimeRange {
+    pub const fn new(since: Option<TimeBound>, until: Option<TimeBound>) -> Self {
+        Self { since, until }
+    }
+
+    /// Parse a range typed into the time range popup. Accepts `last 10m` for the time up to
+    /// 'now', `±30s` for the time around the 'selected' entry, and two bounds like
+    /// `10:15..10:20` where either side can be left out. A single bound is the start of the range
+    pub fn parse(text: &str, now: NaiveDateTime, selected: Option<NaiveDateTime>) -> Result<Self> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(Self::default());
+        }
+        if let Some(duration) = text.strip_prefix("last") {
+            let duration = parse_duration(duration)?;
+            return Ok(Self::new(Some(TimeBound::DateTime(now - duration)), None));
+        }
+        if let Some(duration) = text.strip_prefix('±').or_else(|| text.strip_prefix("+-")) {
+            let duration = parse_duration(duration)?;
+            let selected = selected.context("No entry selected")?;
+            return Ok(Self::new(
+                Some(TimeBound::DateTime(selected - duration)),
+                Some(TimeBound::DateTime(selected + duration)),
+            ));
+        }
+        let (since, until) = text.split_once("..").unwrap_or((text, ""));
+        let bound = |text: &str| {
+            let text = text.trim();
+            if text.is_empty() {
+                Ok(None)
+            } else {
+                TimeBound::parse(text)
+                    .map(Some)
+                    .with_context(|| format!("Invalid time '{text}'"))
+            }
+        };
+        let range = Self::new(bound(since)?, bound(until)?);
+        range.check()?;
+        Ok(range)
+    }
+
+    /// Fails if the range starts after it ends, since nothing could be shown. Bounds of different
+    /// kinds, like a time of day and a date, arent compared
+    pub fn check(&self) -> Result<()> {
+        let (Some(since), Some(until)) = (self.since, self.until) else {
+            return Ok(());
+        };
+        let backwards = match (since, until) {
+            (TimeBound::Time(since), TimeBound::Time(until)) => since > until,
+            (TimeBound::Date(since), TimeBound::Date(until)) => since > until,
+            (TimeBound::DateTime(since), TimeBound::DateTime(until)) => since > until,
+            _ => false,
+        };
+        if backwards {
+            bail!("Start {since} is after the end {until}");
+        }
+        Ok(())
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Returns true if 'timestamp' is within the range
+    pub fn contains(&self, timestamp: &NaiveDateTime) -> bool {
+        self.since
+            .is_none_or(|since| since.compare(timestamp) != Ordering::Less)
+            && self
+                .until
+                .is_none_or(|until| until.compare(timestamp) != Ordering::Greater)
+    }
+}
+
+impl Display for TimeRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(since) = self.since {
+            write!(f, "{since}")?;
+        }
+        f.write_str("..")?;
+        if let Some(until) = self.until {
+            write!(f, "{until}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a bound given on the command line. Besides the times `TimeBound` accepts, a duration
+/// like `10m` means that long before 'now'
+pub fn parse_bound(text: &str, now: NaiveDateTime) -> Result<TimeBound> {
+    TimeBound::parse(text)
+        .or_else(|| {
+            parse_duration(text)
+                .ok()
+                .map(|duration| TimeBound::DateTime(now - duration))
+        })
+        .with_context(|| {
+            format!(
+                "Invalid time '{text}', expected a time like 10:15, a date, or a duration like 10m"
+            )
+        })
+}
+
+/// Parse a duration made up of numbers followed by a unit, like `90s` or `1h30m`. The units are
+/// ms, s, m, h and d
+pub fn parse_duration(text: &str) -> Result<TimeDelta> {
+    let text = text.trim();
+    if text.is_empty() {
+        bail!("Missing duration, like 10m");
+    }
+    let mut total = TimeDelta::zero();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = rest[digits..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(rest.len(), |end| digits + end);
+        let count = rest[..digits]
+            .parse::<i64>()
+            .ok()
+            .with_context(|| format!("Invalid duration '{text}'"))?;
+        let duration = match &rest[digits..unit] {
+            "ms" => TimeDelta::try_milliseconds(count),
+            "s" => TimeDelta::try_seconds(count),
+            "m" => TimeDelta::try_minutes(count),
+            "h" => TimeDelta::try_hours(count),
+            "d" => TimeDelta::try_days(count),
+            _ => None,
+        };
+        total += duration.with_context(|| format!("Invalid duration '{text}'"))?;
+        rest = &rest[unit..];
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90s").unwrap(), TimeDelta::seconds(90));
+        assert_eq!(parse_duration(" 1h30m ").unwrap(), TimeDelta::minutes(90));
+        assert_eq!(parse_duration("2d").unwrap(), TimeDelta::days(2));
+        assert_eq!(
+            parse_duration("250ms").unwrap(),
+            TimeDelta::milliseconds(250)
+        );
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("10x").is_err());
+    }
+
+    #[test]
+    fn bounds_either_side() {
+        let range = TimeRange::parse("10:15..10:20", time("2024-07-12 12:00:00"), None).unwrap();
+        assert!(range.contains(&time("2024-07-12 10:15:00")));
+        assert!(range.contains(&time("2024-07-12 10:20:00")));
+        assert!(!range.contains(&time("2024-07-12 10:20:01")));
+        assert!(!range.contains(&time("2024-07-12 10:14:59")));
+
+        let range = TimeRange::parse("..10:20", time("2024-07-12 12:00:00"), None).unwrap();
+        assert!(range.contains(&time("2024-07-12 00:00:00")));
+        let range = TimeRange::parse("10:15", time("2024-07-12 12:00:00"), None).unwrap();
+        assert!(range.contains(&time("2024-07-12 23:00:00")));
+        assert!(!range.contains(&time("2024-07-12 10:00:00")));
+        assert!(TimeRange::parse(" ", time("2024-07-12 12:00:00"), None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn relative_ranges() {
+        let now = time("2024-07-12 12:00:00");
+        let range = TimeRange::parse("last 10m", now, None).unwrap();
+        assert!(range.contains(&time("2024-07-12 11:50:00")));
+        assert!(!range.contains(&time("2024-07-12 11:49:59")));
+
+        let selected = Some(time("2024-07-12 10:00:00"));
+        let range = TimeRange::parse("±30s", now, selected).unwrap();
+        assert!(range.contains(&time("2024-07-12 09:59:30")));
+        assert!(range.contains(&time("2024-07-12 10:00:30")));
+        assert!(!range.contains(&time("2024-07-12 10:00:31")));
+        assert_eq!(
+            TimeRange::parse("+-30s", now, selected)
+                .unwrap()
+                .to_string(),
+            "2024-07-12 09:59:30..2024-07-12 10:00:30"
+        );
+        assert!(TimeRange::parse("±30s", now, None).is_err());
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        let now = time("2024-07-12 12:00:00");
+        assert_eq!(
+            TimeRange::parse("10:15..soon", now, None)
+                .unwrap_err()
+                .to_string(),
+            "Invalid time 'soon'"
+        );
+        assert!(TimeRange::parse("10:20..10:15", now, None).is_err());
+        assert!(TimeRange::parse("2024-07-13..2024-07-12", now, None).is_err());
+        // A date and a time of day cant be compared, so they are left for the entries to decide
+        assert!(TimeRange::parse("2024-07-13..10:15", now, None).is_ok());
+    }
+
+    #[test]
+    fn command_line_bounds() {
+        let now = time("2024-07-12 12:00:00");
+        assert!(matches!(
+            parse_bound("10:15", now).unwrap(),
+            TimeBound::Time(_)
+        ));
+        assert_eq!(
+            parse_bound("90m", now).unwrap().to_string(),
+            "2024-07-12 10:30:00"
+        );
+        assert!(parse_bound("yesterday", now).is_err());
+    }
+}
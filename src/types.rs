@@ -1,20 +1,45 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    num::NonZeroUsize,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use chrono::NaiveDateTime;
-use itertools::Itertools;
 use ratatui::{
     style::{Style, Stylize},
     text::{Line, Span, ToSpan},
     widgets::{Block, Borders, List, ListItem, ListState},
 };
 
-use anyhow::{anyhow, Result};
-
-use crate::term::app::{App, Dir};
+use crate::{
+    encoding::Encoding,
+    format::LogFormat,
+    parser::get_level,
+    term::app::{App, Dir},
+};
 
 pub type SharedLog = Arc<Mutex<Log>>;
 
-fn log_level_to_span(level: &Arc<str>) -> Span {
+/// Limits on the number of entries and total bytes of text each log keeps, zero for no limit
+static MAX_ENTRIES: AtomicUsize = AtomicUsize::new(0);
+static MAX_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Limit how many entries, or how many bytes of text, each log opened after this keeps. Once a
+/// log goes over either limit its oldest entries are dropped
+pub fn set_log_limits(max_entries: Option<usize>, max_bytes: Option<usize>) {
+    MAX_ENTRIES.store(max_entries.unwrap_or(0), Ordering::Relaxed);
+    MAX_BYTES.store(max_bytes.unwrap_or(0), Ordering::Relaxed);
+}
+
+fn log_level_to_span(level: &Arc<str>) -> Span<'_> {
     let span = level.to_span();
     match &**level {
         " Warning " => span.black().on_light_yellow(),
@@ -23,11 +48,173 @@ fn log_level_to_span(level: &Arc<str>) -> Span {
     }
 }
 
+/// Mark up to 'context' entries that follow each entry that passed the filter as shown, going
+/// through the entries in the order of 'indexes'
+fn mark_context(
+    passes: &[bool],
+    shown: &mut [bool],
+    context: usize,
+    indexes: impl Iterator<Item = usize>,
+) {
+    let mut left = 0;
+    for i in indexes {
+        if passes[i] {
+            left = context;
+        } else if left > 0 {
+            shown[i] = true;
+            left -= 1;
+        }
+    }
+}
+
+/// Scroll 'state' so that the selected row fits in 'height' lines, along with a row either side of
+/// it where there is room, given the height of every row. Returns the range of rows that are on
+/// screen, which start at the new scroll offset
+fn scroll_to_selected(state: &mut ListState, heights: &[usize], height: usize) -> Range<usize> {
+    let Some(last) = heights.len().checked_sub(1) else {
+        state.select(None);
+        *state.offset_mut() = 0;
+        return 0..0;
+    };
+    let selected = state.selected().map(|selected| selected.min(last));
+    state.select(selected);
+    let mut first = state.offset().min(last);
+    if let Some(selected) = selected {
+        let below = (selected + 1).min(last);
+        first = first.min(selected.saturating_sub(1));
+        let mut used = heights[first..=below].iter().sum::<usize>();
+        while first < selected && used > height {
+            used -= heights[first];
+            first += 1;
+        }
+    }
+    *state.offset_mut() = first;
+
+    let mut end = first;
+    let mut used = 0;
+    while end < heights.len()
+        && (selected.is_some_and(|selected| end <= selected) || used + heights[end] <= height)
+    {
+        used += heights[end];
+        end += 1;
+    }
+    first..end
+}
+
+/// Split 'line', which starts at byte 'start' of the text of an entry, into spans with the parts
+/// covered by 'highlights' styled so that they stand out
+fn highlight_spans<'a>(line: &'a str, start: usize, highlights: &[Range<usize>]) -> Vec<Span<'a>> {
+    let end = start + line.len();
+    let mut spans = Vec::new();
+    let mut position = start;
+    for range in highlights
+        .iter()
+        .skip_while(|range| range.end <= start)
+        .take_while(|range| range.start < end)
+    {
+        let from = range.start.max(start);
+        let to = range.end.min(end);
+        if from > position {
+            spans.push(Span::from(&line[position - start..from - start]));
+        }
+        spans.push(
+            Span::from(&line[from - start..to - start])
+                .black()
+                .on_yellow(),
+        );
+        position = to;
+    }
+    if position < end || spans.is_empty() {
+        spans.push(Span::from(&line[position - start..]));
+    }
+    spans
+}
+
+/// Text of an entry. In low memory mode entries keep where their text is in the file they were read
+/// from instead of a copy of it, and read it back from the file each time it's needed
+#[derive(Debug)]
+enum Text {
+    Owned(String),
+    Stored { file: Arc<File>, range: Range<u64> },
+}
+
+impl Text {
+    fn as_str(&self) -> Cow<'_, str> {
+        match self {
+            Self::Owned(text) => Cow::Borrowed(text),
+            Self::Stored { file, range } => read_range(file, range).map_or_else(
+                |err| Cow::Owned(format!("<Failed to read entry from the file: {err}>")),
+                Cow::Owned,
+            ),
+        }
+    }
+
+    /// Length of the text in bytes, without reading it back for stored text
+    fn len(&self) -> usize {
+        match self {
+            Self::Owned(text) => text.len(),
+            Self::Stored { range, .. } => usize::try_from(range.end - range.start).unwrap_or(0),
+        }
+    }
+
+    /// Add 'input' as a new line at the end of the text. 'stored' is the same text as a range of
+    /// the file if it can be kept that way and it's the line straight after the end of the text,
+    /// in which case the range is extended over the line ending rather than copying the text
+    fn push_line(&mut self, input: &str, stored: Option<&Self>) {
+        if let (
+            Self::Stored { file, range },
+            Some(Self::Stored {
+                file: input_file,
+                range: input_range,
+            }),
+        ) = (&mut *self, stored)
+        {
+            // Only the line ending is between them, which is two bytes for '\r\n'. The '\r' is
+            // read back along with the text and trimmed off again when it's shown
+            if Arc::ptr_eq(file, input_file) && range.end < input_range.start {
+                range.end = input_range.end;
+                return;
+            }
+        }
+        match self {
+            Self::Owned(text) => {
+                text.push('\n');
+                text.push_str(input);
+            }
+            Self::Stored { .. } => {
+                *self = Self::Owned(format!("{}\n{input}", self.as_str()));
+            }
+        }
+    }
+}
+
+/// Read the text in 'range' of 'file'. Fails rather than returning the wrong text if the file has
+/// been truncated since it was read
+fn read_range(mut file: &File, range: &Range<u64>) -> io::Result<String> {
+    let len = range.end - range.start;
+    let mut bytes = Vec::with_capacity(usize::try_from(len).unwrap_or(0));
+    file.seek(SeekFrom::Start(range.start))?;
+    file.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the file has been truncated",
+        ));
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
 #[derive(Debug)]
 pub struct Entry {
     timestamp: NaiveDateTime,
     level: Arc<str>,
-    data: String,
+    data: Text,
+    /// Line of the file the entry starts on, starting at 1
+    line: usize,
+    /// Number of lines of the file the entry spans
+    line_count: usize,
+    /// Offset in bytes of the start of the entry in the file
+    offset: u64,
 }
 
 impl Entry {
@@ -39,46 +226,236 @@ impl Entry {
         &self.level
     }
 
-    pub fn log_data(&self) -> &str {
-        &self.data
+    pub fn log_data(&self) -> Cow<'_, str> {
+        self.data.as_str()
+    }
+
+    /// Returns true if the entry spans line 'line' of its file
+    pub const fn contains_line(&self, line: usize) -> bool {
+        self.line <= line && line < self.line + self.line_count
     }
 
     pub(crate) fn new(timestamp: NaiveDateTime, level: Arc<str>, data: &str) -> Self {
         Self {
             timestamp,
             level,
-            data: data.to_string(),
+            data: Text::Owned(data.to_string()),
+            line: 0,
+            line_count: 1,
+            offset: 0,
         }
     }
 
-    pub fn as_list_item(&self) -> ListItem {
-        let mut data = self.data.lines();
-        let mut out_lines = Vec::with_capacity(data.clone().count());
-        out_lines.push(Line::from(vec![
+    /// Lines the entry is shown with in the entries list. 'highlights' are sorted byte ranges of
+    /// the text to highlight, which can carry on over several lines
+    pub fn as_lines(&self, highlights: &[Range<usize>]) -> Vec<Line<'_>> {
+        match self.log_data() {
+            Cow::Borrowed(data) => self.lines_of(data, highlights),
+            // Text read back from the file doesnt live as long as the entry, so the lines need
+            // their own copy of it
+            Cow::Owned(data) => self
+                .lines_of(&data, highlights)
+                .into_iter()
+                .map(|line| {
+                    Line::from(
+                        line.spans
+                            .into_iter()
+                            .map(|span| Span::styled(span.content.into_owned(), span.style))
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn lines_of<'a>(&'a self, data: &'a str, highlights: &[Range<usize>]) -> Vec<Line<'a>> {
+        let mut lines = data.split('\n').scan(0, |start, line| {
+            let line_start = *start;
+            *start += line.len() + 1;
+            Some(highlight_spans(
+                line.trim_end_matches('\r'),
+                line_start,
+                highlights,
+            ))
+        });
+        let mut out_lines = Vec::with_capacity(data.lines().count().max(1));
+        let mut first = vec![
             self.timestamp().to_span().black().on_dark_gray(),
             log_level_to_span(&self.level),
-            Span::from(data.next().unwrap()),
-        ]));
+        ];
+        first.extend(lines.next().unwrap_or_default());
+        out_lines.push(Line::from(first));
+
+        out_lines.extend(lines.map(Line::from));
+
+        out_lines
+    }
 
-        data.for_each(|line| out_lines.push(Line::from(line)));
+    /// Everything known about the entry, for showing it in full. 'source' describes where it was
+    /// read from
+    pub fn detail_lines(&self, source: &str) -> Vec<Line<'_>> {
+        let lines = if self.line_count > 1 {
+            format!("{}-{}", self.line, self.line + self.line_count - 1)
+        } else {
+            self.line.to_string()
+        };
+        let mut out_lines = vec![
+            Line::from(vec!["Source: ".bold(), Span::from(source.to_string())]),
+            Line::from(vec!["Timestamp: ".bold(), self.timestamp.to_span()]),
+            Line::from(vec!["Level: ".bold(), Span::from(self.level.trim())]),
+            Line::from(vec![
+                "Lines: ".bold(),
+                Span::from(format!("{lines} ({} total)", self.line_count)),
+            ]),
+            Line::from(vec![
+                "Byte offset: ".bold(),
+                Span::from(self.offset.to_string()),
+            ]),
+            Line::default(),
+        ];
+        out_lines.extend(
+            self.log_data()
+                .lines()
+                .map(|line| Line::from(line.to_string())),
+        );
+        out_lines
+    }
+}
 
+/// A problem found while reading a log that didnt stop the rest of it from loading
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// Line of the file the problem was found on, starting at 1. Zero if it's not tied to a line
+    line: usize,
+    text: String,
+    reason: String,
+}
+
+impl Diagnostic {
+    pub fn as_list_item(&self, log_name: &str) -> ListItem<'_> {
+        let location = if self.line == 0 {
+            format!("{log_name} ")
+        } else {
+            format!("{log_name}:{} ", self.line)
+        };
+        let mut out_lines = vec![Line::from(vec![
+            Span::from(location).bold(),
+            Span::from(self.reason.as_str()).light_red(),
+        ])];
+        if !self.text.is_empty() {
+            out_lines.push(Line::from(self.text.as_str()).dim());
+        }
         ListItem::new(out_lines)
     }
 }
 
+/// A run of entries from one file, or from one launch of the program writing the log
+#[derive(Debug)]
+pub struct Session {
+    /// Index of the first entry in the session
+    start: usize,
+    label: String,
+    /// File on disk the entries of the session were read from, if there is one
+    path: Option<PathBuf>,
+}
+
+impl Session {
+    pub const fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Marker shown above the first entry of the session
+    pub fn as_line(&self) -> Line<'_> {
+        Line::from(format!(" {} ", self.label))
+            .centered()
+            .black()
+            .on_light_blue()
+    }
+}
+
+/// A row of the entries list, before it has been built
+struct Row<'a> {
+    /// Index of the entry shown on the row
+    index: usize,
+    /// Whether the entry is only shown as context around an entry that passed the filter
+    context: bool,
+    /// Session that starts with the entry, shown above it
+    marker: Option<&'a Session>,
+    /// Whether there is a gap between this entry and the one on the row before
+    separator: bool,
+}
+
 #[derive(Debug)]
 pub struct Log {
     name: String,
-    entries: Vec<Entry>,
+    /// File on disk that is currently being read into the log, if there is one
+    path: Option<PathBuf>,
+    format: Arc<dyn LogFormat>,
+    /// Encoding the file was decoded with, `None` until it has been read
+    encoding: Option<Encoding>,
+    entries: VecDeque<Entry>,
     list_state: ListState,
+    /// Selected row of the entries list when it was last drawn, and the number of the entry on it
+    selection_anchor: Option<(usize, usize)>,
+    /// Number of an entry to select when the entries list is next drawn
+    jump_to: Option<usize>,
+    /// Number of entries that have been dropped from the start of the log to stay within the limits
+    dropped: usize,
+    /// Total length of the text of the entries
+    bytes: usize,
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+    /// Number of lines that have been passed to the parser so far
+    lines_read: usize,
+    /// Offset in the file of the line being parsed
+    line_offset: u64,
+    /// Offset in the file of the next byte to be parsed
+    file_position: u64,
+    diagnostics: Vec<Diagnostic>,
+    sessions: Vec<Session>,
+    /// Whether the sessions are listed under the log in the Files list
+    expanded: bool,
+    /// Set for logs that were found after startup, until they are selected
+    new: bool,
+    /// Percentage of the existing file contents read so far, `None` once it has been loaded
+    loading: Option<u8>,
+    /// File that is being read into the log, when entries keep ranges of it rather than their text
+    stored: Option<Arc<File>>,
 }
 
 impl Log {
-    pub fn new<T: ToString>(name: &T) -> Self {
+    pub fn new<T: ToString>(name: &T, format: Arc<dyn LogFormat>) -> Self {
         Self {
             name: name.to_string(),
-            entries: Vec::new(),
+            path: None,
+            format,
+            encoding: None,
+            entries: VecDeque::new(),
             list_state: ListState::default().with_selected(Some(0)),
+            selection_anchor: None,
+            jump_to: None,
+            dropped: 0,
+            bytes: 0,
+            max_entries: NonZeroUsize::new(MAX_ENTRIES.load(Ordering::Relaxed))
+                .map(NonZeroUsize::get),
+            max_bytes: NonZeroUsize::new(MAX_BYTES.load(Ordering::Relaxed)).map(NonZeroUsize::get),
+            lines_read: 0,
+            line_offset: 0,
+            file_position: 0,
+            diagnostics: Vec::new(),
+            sessions: Vec::new(),
+            expanded: false,
+            new: false,
+            loading: None,
+            stored: None,
         }
     }
 
@@ -86,53 +463,768 @@ impl Log {
         &self.name
     }
 
-    pub fn entries(&self) -> &[Entry] {
+    pub fn set_path(&mut self, path: Option<&Path>) {
+        self.path = path.map(Path::to_path_buf);
+    }
+
+    pub fn format(&self) -> &Arc<dyn LogFormat> {
+        &self.format
+    }
+
+    pub fn set_format(&mut self, format: Arc<dyn LogFormat>) {
+        self.format = format;
+    }
+
+    pub const fn encoding(&self) -> Option<Encoding> {
+        self.encoding
+    }
+
+    pub const fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = Some(encoding);
+    }
+
+    pub const fn entries(&self) -> &VecDeque<Entry> {
         &self.entries
     }
 
-    pub fn add_entry(&mut self, entry: Entry) {
-        self.entries.push(entry);
+    /// Add 'entry', which was parsed from 'line'. While a file is being read in low memory mode,
+    /// text at the end of the line is kept as a range of the file rather than copied
+    pub fn add_entry(&mut self, mut entry: Entry, line: &str) {
+        entry.line = self.lines_read;
+        entry.offset = self.line_offset;
+        let len = entry.data.len();
+        if line.ends_with(&*entry.log_data()) {
+            if let Some(text) = self.stored_text(line, len) {
+                entry.data = text;
+            }
+        }
+        self.bytes += len;
+        self.entries.push_back(entry);
+        self.enforce_limits();
+    }
+
+    /// Drop the oldest entries while the log is over its entry or byte limit. The newest entry is
+    /// always kept even if it's bigger than the byte limit on its own
+    fn enforce_limits(&mut self) {
+        let mut count = 0;
+        while self.entries.len() > 1
+            && (self.max_entries.is_some_and(|max| self.entries.len() > max)
+                || self.max_bytes.is_some_and(|max| self.bytes > max))
+        {
+            let entry = self.entries.pop_front().unwrap();
+            self.bytes -= entry.data.len();
+            count += 1;
+        }
+        if count == 0 {
+            return;
+        }
+        self.dropped += count;
+
+        // Session starts move back with the entries, sessions that have been dropped completely
+        // are removed
+        for session in &mut self.sessions {
+            session.start = session.start.saturating_sub(count);
+        }
+        while self.sessions.get(1).is_some_and(|next| next.start == 0) {
+            self.sessions.remove(0);
+        }
+    }
+
+    /// Set the file that the lines being parsed are read from in low memory mode, or `None` once
+    /// it has been read
+    pub fn set_stored(&mut self, stored: Option<Arc<File>>) {
+        self.stored = stored;
+    }
+
+    /// The last 'len' bytes of the current 'line' as a range of the file being read, if entries
+    /// are being stored that way. Only lines that decoded to exactly the bytes in the file can be
+    /// stored, so other encodings and lines with invalid text are copied instead
+    fn stored_text(&self, line: &str, len: usize) -> Option<Text> {
+        let file = self.stored.as_ref()?;
+        let bom = match self.encoding? {
+            Encoding::Utf8Bom if self.line_offset == 0 => 3,
+            Encoding::Utf8 | Encoding::Utf8Bom => 0,
+            _ => return None,
+        };
+        if line.contains(char::REPLACEMENT_CHARACTER) {
+            return None;
+        }
+        let end = self.line_offset + bom + line.len() as u64;
+        Some(Text::Stored {
+            file: file.clone(),
+            range: end - len as u64..end,
+        })
+    }
+
+    /// State to draw the entries list with, given the number of the entry on each row. If the
+    /// selection hasnt been moved since the list was last drawn it stays on the same entry, so it
+    /// doesnt move when the oldest entries are dropped
+    fn list_state_for(&self, rows: &[usize]) -> ListState {
+        let mut state = self.list_state.clone();
+        if let Some(number) = self.jump_to {
+            // Entries hidden by the filter cant be selected, so the next one shown is instead
+            state.select(Some(rows.partition_point(|row| *row < number)));
+        } else if let Some((row, number)) = self.selection_anchor {
+            if state.selected() == Some(row) {
+                let anchored = rows.binary_search(&number).unwrap_or_else(|row| row);
+                *state.offset_mut() = state.offset().saturating_sub(row.saturating_sub(anchored));
+                state.select(Some(anchored));
+            }
+        }
+        state
+    }
+
+    /// Number of entries dropped from the start of the log, entries are numbered from the first
+    /// one ever added so the number of an entry is its index plus this
+    pub const fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Number of the entry selected in the entries list when it was last drawn
+    pub fn selected_number(&self) -> Option<usize> {
+        self.selection_anchor.map(|(_, number)| number)
+    }
+
+    /// Select the entry with the number 'number' when the entries list is next drawn
+    pub const fn jump_to_entry(&mut self, number: usize) {
+        self.jump_to = Some(number);
+    }
+
+    /// The entry selected in the entries list when it was last drawn
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        self.selection_anchor
+            .and_then(|(_, number)| self.entries.get(number.checked_sub(self.dropped)?))
+    }
+
+    /// Session the selected entry belongs to, if the log has any
+    fn selected_session(&self) -> Option<&Session> {
+        let (_, number) = self.selection_anchor?;
+        let index = number.saturating_sub(self.dropped);
+        let session = self
+            .sessions
+            .partition_point(|session| session.start <= index)
+            .checked_sub(1)?;
+        self.sessions.get(session)
+    }
+
+    /// Where the selected entry came from, the log name followed by the label of the session it
+    /// belongs to if there is one
+    pub fn selected_source(&self) -> String {
+        self.selected_session().map_or_else(
+            || self.name.clone(),
+            |session| format!("{} - {}", self.name, session.label),
+        )
+    }
+
+    /// Path of the file the selected entry was read from and the line it starts on, `None` if
+    /// nothing is selected or the entry didnt come from a file on disk
+    pub fn selected_location(&self) -> Option<(&Path, usize)> {
+        let entry = self.selected_entry()?;
+        let path = self
+            .selected_session()
+            .map_or(self.path.as_deref(), Session::path)?;
+        Some((path, entry.line))
     }
 
-    pub fn list_state_mut(&mut self) -> &mut ListState {
+    /// Select the first entry in 'range' that spans line 'line' of its file, or the first entry
+    /// after it. The selection moves when the entries list is next drawn. Returns false if there
+    /// is no such entry
+    pub fn select_line(&mut self, line: usize, range: Range<usize>) -> bool {
+        let found = self
+            .entries
+            .range(range.clone())
+            .position(|entry| entry.contains_line(line) || entry.line > line);
+        if let Some(index) = found {
+            self.jump_to = Some(self.dropped + range.start + index);
+        }
+        found.is_some()
+    }
+
+    /// Keep 'state' after the entries list has been drawn with it, see `list_state_for`
+    pub fn set_list_state(&mut self, state: ListState, rows: &[usize]) {
+        self.selection_anchor = state
+            .selected()
+            .and_then(|row| rows.get(row).map(|number| (row, *number)));
+        self.list_state = state;
+        self.jump_to = None;
+    }
+
+    pub const fn list_state_mut(&mut self) -> &mut ListState {
         &mut self.list_state
     }
 
-    pub fn append_last(&mut self, input: &str) -> Result<()> {
-        let data = &mut self
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Record a problem with the current line, whose text is 'text'
+    pub fn add_diagnostic<T: Into<String>>(&mut self, text: &str, reason: T) {
+        self.diagnostics.push(Diagnostic {
+            line: self.lines_read,
+            text: text.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    /// Record a problem with the file as a whole rather than one of its lines
+    pub fn add_file_diagnostic<T: Into<String>>(&mut self, reason: T) {
+        self.diagnostics.push(Diagnostic {
+            line: 0,
+            text: String::new(),
+            reason: reason.into(),
+        });
+    }
+
+    /// Move on to the next line of the file, which starts at 'offset'. Should be called once for
+    /// every line read from the file so that entries and diagnostics point at the right line
+    pub const fn next_line(&mut self, offset: u64) {
+        self.lines_read += 1;
+        self.line_offset = offset;
+    }
+
+    pub const fn file_position(&self) -> u64 {
+        self.file_position
+    }
+
+    pub const fn set_file_position(&mut self, position: u64) {
+        self.file_position = position;
+    }
+
+    pub fn sessions(&self) -> &[Session] {
+        &self.sessions
+    }
+
+    /// Range of entry indexes that belong to the session at 'index'. Sessions can be dropped along
+    /// with their entries after the index was looked up, in which case the whole log is used
+    pub fn session_range(&self, index: usize) -> Range<usize> {
+        let Some(session) = self.sessions.get(index) else {
+            return 0..self.entries.len();
+        };
+        let end = self
+            .sessions
+            .get(index + 1)
+            .map_or(self.entries.len(), Session::start);
+        session.start..end
+    }
+
+    pub const fn expanded(&self) -> bool {
+        self.expanded
+    }
+
+    pub const fn toggle_expanded(&mut self) {
+        self.expanded = !self.expanded;
+    }
+
+    pub const fn mark_new(&mut self) {
+        self.new = true;
+    }
+
+    pub const fn mark_seen(&mut self) {
+        self.new = false;
+    }
+
+    pub const fn set_loading(&mut self, loading: Option<u8>) {
+        self.loading = loading;
+    }
+
+    /// Start a new session, the entries added after this belong to it. If the log already has
+    /// entries but no sessions, those entries are put in a session of their own first. Starting a
+    /// session before the previous one has any entries combines the two, which happens when a new
+    /// file starts with a startup banner
+    pub fn start_session(&mut self, label: &str) {
+        if self.sessions.is_empty() && !self.entries.is_empty() {
+            self.sessions.push(Session {
+                start: 0,
+                label: self.name.clone(),
+                path: self.path.clone(),
+            });
+        }
+        match self.sessions.last_mut() {
+            Some(session) if session.start == self.entries.len() => {
+                session.label = format!("{} - {label}", session.label);
+            }
+            _ => self.sessions.push(Session {
+                start: self.entries.len(),
+                label: label.to_string(),
+                path: self.path.clone(),
+            }),
+        }
+    }
+
+    /// Start a session for a new file being read into the log, line numbers start again from the
+    /// top. 'path' is where the file is on disk, if it's a file that can be opened
+    pub fn start_file(&mut self, label: &str, path: Option<&Path>) {
+        self.lines_read = 0;
+        self.file_position = 0;
+        self.start_session(label);
+        self.set_path(path);
+        if let Some(session) = self.sessions.last_mut() {
+            session.path.clone_from(&self.path);
+        }
+    }
+
+    /// Append 'input' to the data of the last entry. If there is no entry yet in the current
+    /// session, as happens when a file doesnt start with a header, a preamble entry is created to
+    /// hold the text instead
+    pub fn append_last(&mut self, input: &str) {
+        let session_start = self.sessions.last().map_or(0, Session::start);
+        let stored = self.stored_text(input, input.len());
+        if let Some(entry) = self.entries.range_mut(session_start..).next_back() {
+            let len = entry.data.len();
+            // Lines that werent added to the entry can be between it and this one
+            let follows = entry.line + entry.line_count == self.lines_read;
+            entry
+                .data
+                .push_line(input, stored.as_ref().filter(|_| follows));
+            entry.line_count += 1;
+            self.bytes += entry.data.len() - len;
+            self.enforce_limits();
+        } else {
+            self.add_diagnostic(input, "Text before the first entry was kept in a preamble");
+            let preamble = Entry::new(NaiveDateTime::default(), get_level("Preamble"), input);
+            self.add_entry(preamble, input);
+        }
+    }
+
+    /// Indexes of the entries shown in the entries list for the session at 'session', or the
+    /// whole log if there isnt one. Along with the entries that pass the filter, up to the
+    /// context count of entries either side of them are shown, which is returned with each index
+    pub fn shown_entries(&self, app: &App, session: Option<usize>) -> Vec<(usize, bool)> {
+        let range = session.map_or(0..self.entries.len(), |session| self.session_range(session));
+        let passes = self
             .entries
-            .last_mut()
-            .ok_or_else(|| anyhow!("Log must have previous entry to append to"))?
-            .data;
-        data.push('\n');
-        data.push_str(input);
-        Ok(())
+            .range(range.clone())
+            .map(|entry| app.filter(&self.name, entry))
+            .collect::<Vec<_>>();
+
+        let mut shown = passes.clone();
+        if app.context > 0 {
+            mark_context(&passes, &mut shown, app.context, 0..passes.len());
+            mark_context(&passes, &mut shown, app.context, (0..passes.len()).rev());
+        }
+        shown
+            .into_iter()
+            .zip(passes)
+            .zip(range)
+            .filter(|((shown, _), _)| *shown)
+            .map(|((_, passes), i)| (i, !passes))
+            .collect()
     }
 
-    pub fn get_list(&self, app: &App) -> List {
+    /// Build the list of entries that pass the filter, limited to the session at 'session' if
+    /// there is one. Entries shown as context are dimmed, with a separator between groups of
+    /// entries that arent next to each other. Only the rows that fit in 'height' lines are built,
+    /// so the text of the other entries isnt read. The list starts at the scroll offset of the
+    /// returned state, which is for the whole list along with the number of the entry on each row
+    /// of it, counting the entries that have been dropped
+    pub fn get_list(
+        &self,
+        app: &App,
+        session: Option<usize>,
+        height: usize,
+    ) -> (List<'_>, Vec<usize>, ListState) {
         let style = match app.cursor() {
             Dir::Left => Style::new().reversed().dim(),
             Dir::Right => Style::new().reversed(),
         };
 
-        List::new(
-            self.entries()
+        // Each session marker goes above the first entry of the session that isnt filtered out
+        let mut sessions = self.sessions.iter().peekable();
+        let mut previous = None;
+        // Entries that match the find input, and which of them is selected. An entry that is
+        // about to be jumped to counts as selected already
+        let selected = self.jump_to.or_else(|| self.selected_number());
+        let mut found = 0;
+        let mut current = None;
+        let rows = self
+            .shown_entries(app, session)
+            .into_iter()
+            .map(|(index, context)| {
+                let mut marker = None;
+                while let Some(session) = sessions.next_if(|session| session.start() <= index) {
+                    marker = Some(session);
+                }
+                if app.find_matches(&self.name, &self.entries[index]) {
+                    found += 1;
+                    if selected == Some(self.dropped + index) {
+                        current = Some(found);
+                    }
+                }
+                let separator =
+                    app.context > 0 && previous.is_some_and(|previous| previous + 1 != index);
+                previous = Some(index);
+                Row {
+                    index,
+                    context,
+                    marker,
+                    separator,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let numbers = rows
+            .iter()
+            .map(|row| self.dropped + row.index)
+            .collect::<Vec<_>>();
+        let heights = rows
+            .iter()
+            .map(|row| {
+                self.entries[row.index].line_count
+                    + usize::from(row.marker.is_some())
+                    + usize::from(row.separator)
+            })
+            .collect::<Vec<_>>();
+        let mut state = self.list_state_for(&numbers);
+        let items = rows[scroll_to_selected(&mut state, &heights, height)]
+            .iter()
+            .map(|row| {
+                let entry = &self.entries[row.index];
+                let mut lines = entry.as_lines(&app.highlights(entry));
+                if row.context {
+                    lines = lines.into_iter().map(Stylize::dim).collect();
+                }
+                if let Some(session) = row.marker {
+                    lines.insert(0, session.as_line());
+                }
+                if row.separator {
+                    lines.insert(0, Line::from("--").dim());
+                }
+                ListItem::new(lines)
+            })
+            .collect::<Vec<_>>();
+
+        let title = if self.dropped == 0 {
+            "Log".to_string()
+        } else {
+            format!("Log - {} oldest entries dropped", self.dropped)
+        };
+        let title = match current {
+            _ if !app.finding() => title,
+            Some(current) => format!("{title} - match {current} of {found}"),
+            None if found == 0 => format!("{title} - no matches"),
+            None => format!("{title} - {found} matches"),
+        };
+        let list = List::new(items)
+            .block(
+                Block::new()
+                    .borders(Borders::all())
+                    .title(title)
+                    .title_style(Style::new().bold()),
+            )
+            .highlight_style(style)
+            .scroll_padding(1);
+        (list, numbers, state)
+    }
+
+    pub fn as_list_item(&self) -> ListItem<'_> {
+        let expander = match (self.sessions.is_empty(), self.expanded) {
+            (true, _) => "  ",
+            (false, false) => "▸ ",
+            (false, true) => "▾ ",
+        };
+        let mut spans = vec![
+            Span::from(expander),
+            Span::from(self.name()),
+            Span::from(format!(" [{}]", self.format.name())).dim(),
+        ];
+        if let Some(encoding) = self.encoding {
+            spans.push(Span::from(format!(" {encoding}")).dim());
+        }
+        if !self.diagnostics.is_empty() {
+            spans.push(Span::from(format!(" !{}", self.diagnostics.len())).light_red());
+        }
+        if let Some(percent) = self.loading {
+            spans.push(Span::from(format!(" loading {percent}%")).yellow());
+        }
+        if self.new {
+            spans.push(Span::from(" new").light_green().bold());
+        }
+        ListItem::new(Line::from(spans))
+    }
+
+    /// Row shown under the log in the Files list for the session at 'index'
+    pub fn session_list_item(&self, index: usize) -> ListItem<'_> {
+        let label = self.sessions.get(index).map_or("", Session::label);
+        ListItem::new(Line::from(vec![
+            Span::from("    "),
+            Span::from(label),
+            Span::from(format!(" ({})", self.session_range(index).len())).dim(),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        format::{find_format, PlainText},
+        parser::parse_bytes,
+    };
+
+    /// An empty log that keeps at most the given number of entries and bytes of text
+    fn limited_log(max_entries: Option<usize>, max_bytes: Option<usize>) -> Log {
+        let mut log = Log::new(&"test", Arc::new(PlainText::new(NaiveDateTime::default())));
+        log.max_entries = max_entries;
+        log.max_bytes = max_bytes;
+        log
+    }
+
+    fn add(log: &mut Log, text: &str) {
+        log.add_entry(
+            Entry::new(NaiveDateTime::default(), get_level("Info"), text),
+            text,
+        );
+    }
+
+    fn texts(log: &Log) -> Vec<String> {
+        log.entries()
+            .iter()
+            .map(|entry| entry.log_data().into_owned())
+            .collect()
+    }
+
+    /// Parse 'contents' as a `SteamVR` log in low memory mode, with entries kept as ranges of the
+    /// temporary file at the returned path
+    fn stored_log(name: &str, contents: &str) -> (Log, PathBuf) {
+        let path = std::env::temp_dir().join(format!("valve_log_viewer_{name}.txt"));
+        std::fs::write(&path, contents).unwrap();
+        let mut log = Log::new(&name, find_format("steamvr").unwrap());
+        log.set_encoding(Encoding::Utf8);
+        log.set_stored(Some(Arc::new(File::open(&path).unwrap())));
+        parse_bytes(&mut log, contents.as_bytes());
+        (log, path)
+    }
+
+    #[test]
+    fn continuation_lines_extend_the_stored_range_with_either_line_ending() {
+        for (name, ending) in [("lf", "\n"), ("crlf", "\r\n")] {
+            let (log, path) = stored_log(
+                name,
+                &format!(
+                    "Fri Jul 12 2024 10:15:00.456 [Info] - one{ending}  two{ending}  three{ending}"
+                ),
+            );
+            let entry = &log.entries()[0];
+            assert!(matches!(entry.data, Text::Stored { .. }), "{name}");
+            assert_eq!(entry.log_data(), format!("one{ending}  two{ending}  three"));
+            let lines = entry.as_lines(&[]);
+            assert_eq!(lines.len(), 3);
+            assert_eq!(lines[2].to_string(), "  three");
+            drop(log);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn scrolling_keeps_the_selected_row_and_its_neighbours_on_screen() {
+        let heights = [1, 3, 1, 1, 2, 1, 1];
+        let mut state = ListState::default().with_selected(Some(4));
+        assert_eq!(scroll_to_selected(&mut state, &heights, 5), 2..6);
+        assert_eq!(state.offset(), 2);
+
+        // Moving up past the top scrolls so the row above it shows as well
+        state.select(Some(2));
+        assert_eq!(scroll_to_selected(&mut state, &heights, 5), 1..4);
+
+        // A selection past the end is moved onto the last row
+        state.select(Some(usize::MAX));
+        assert_eq!(scroll_to_selected(&mut state, &heights, 5), 3..7);
+        assert_eq!(state.selected(), Some(6));
+
+        assert_eq!(scroll_to_selected(&mut state, &[], 5), 0..0);
+        assert_eq!(state.selected(), None);
+    }
+
+    #[test]
+    fn the_oldest_entries_are_dropped_past_the_entry_limit() {
+        let mut log = limited_log(Some(3), None);
+        for text in ["0", "1", "2", "3", "4"] {
+            add(&mut log, text);
+        }
+        assert_eq!(texts(&log), ["2", "3", "4"]);
+        assert_eq!(log.dropped(), 2);
+        assert_eq!(log.bytes, 3);
+    }
+
+    #[test]
+    fn the_oldest_entries_are_dropped_past_the_byte_limit() {
+        let mut log = limited_log(None, Some(10));
+        for text in ["aaaa", "bbbb", "cccc", "dddd"] {
+            add(&mut log, text);
+        }
+        assert_eq!(texts(&log), ["cccc", "dddd"]);
+        assert_eq!(log.bytes, 8);
+
+        // Continuation lines count towards the limit too
+        log.append_last("eeee");
+        assert_eq!(texts(&log), ["dddd\neeee"]);
+        assert_eq!(log.bytes, 9);
+
+        // The newest entry is kept even when it's over the limit on its own
+        add(&mut log, "a much longer entry");
+        assert_eq!(texts(&log), ["a much longer entry"]);
+        assert_eq!(log.dropped(), 4);
+    }
+
+    #[test]
+    fn sessions_move_back_with_their_entries_and_go_once_they_are_empty() {
+        let mut log = limited_log(Some(3), None);
+        log.start_session("first");
+        add(&mut log, "a0");
+        add(&mut log, "a1");
+        log.start_session("second");
+        add(&mut log, "b0");
+        add(&mut log, "b1");
+        let labels = |log: &Log| {
+            log.sessions()
                 .iter()
-                .filter(|entry| app.filter(entry))
-                .map(Entry::as_list_item)
-                .collect_vec(),
-        )
-        .block(
-            Block::new()
-                .borders(Borders::all())
-                .title("Log")
-                .title_style(Style::new().bold()),
-        )
-        .highlight_style(style)
-        .scroll_padding(1)
+                .map(|session| session.label().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels(&log), ["first", "second"]);
+        assert_eq!(log.session_range(0), 0..1);
+        assert_eq!(log.session_range(1), 1..3);
+
+        add(&mut log, "b2");
+        assert_eq!(labels(&log), ["second"]);
+        assert_eq!(log.session_range(0), 0..3);
+        // An index looked up before the session was dropped falls back to the whole log
+        assert_eq!(log.session_range(1), 0..3);
+        assert_eq!(log.session_list_item(1).height(), 1);
+    }
+
+    #[test]
+    fn the_selection_stays_on_the_same_entry_as_entries_are_dropped() {
+        let mut log = limited_log(Some(5), None);
+        for text in ["0", "1", "2", "3", "4"] {
+            add(&mut log, text);
+        }
+        let rows = |log: &Log| {
+            (0..log.entries().len())
+                .map(|i| log.dropped() + i)
+                .collect::<Vec<_>>()
+        };
+        let mut state = ListState::default().with_selected(Some(3));
+        *state.offset_mut() = 2;
+        log.set_list_state(state, &rows(&log));
+        assert_eq!(log.selected_number(), Some(3));
+
+        add(&mut log, "5");
+        add(&mut log, "6");
+        let state = log.list_state_for(&rows(&log));
+        assert_eq!(state.selected(), Some(1));
+        assert_eq!(state.offset(), 0);
+        log.set_list_state(state, &rows(&log));
+        assert_eq!(log.selected_entry().unwrap().log_data(), "3");
+
+        // Once the selected entry itself is dropped the selection stays at the top
+        for text in ["7", "8", "9"] {
+            add(&mut log, text);
+        }
+        let state = log.list_state_for(&rows(&log));
+        assert_eq!(state.selected(), Some(0));
+
+        // Moving the selection isnt undone by the anchor
+        log.list_state_mut().select(Some(4));
+        assert_eq!(log.list_state_for(&rows(&log)).selected(), Some(4));
+    }
+
+    /// Which entries are shown with 'context' entries either side of the ones that pass, as a
+    /// string with 'x' for entries that pass and 'c' for the context shown around them
+    fn shown_context(passes: &str, context: usize) -> String {
+        let passes = passes.chars().map(|c| c == 'x').collect::<Vec<_>>();
+        let mut shown = passes.clone();
+        mark_context(&passes, &mut shown, context, 0..passes.len());
+        mark_context(&passes, &mut shown, context, (0..passes.len()).rev());
+        passes
+            .iter()
+            .zip(shown)
+            .map(|(passes, shown)| match (passes, shown) {
+                (true, _) => 'x',
+                (false, true) => 'c',
+                (false, false) => '.',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn context_is_marked_either_side_of_each_match() {
+        // Windows that overlap or touch join up into one group
+        assert_eq!(shown_context("x......x..x", 2), "xcc..ccxccx");
+        assert_eq!(shown_context("x....x", 2), "xccccx");
+        // Matches at either end of the list only get context on the inside
+        assert_eq!(shown_context("x.....", 2), "xcc...");
+        assert_eq!(shown_context(".....x", 2), "...ccx");
+        assert_eq!(shown_context("..x..", 0), "..x..");
+    }
+
+    #[test]
+    fn groups_of_entries_that_arent_next_to_each_other_are_separated() {
+        let mut log = limited_log(None, None);
+        for text in [
+            "x0", "hit1", "x2", "x3", "x4", "x5", "x6", "x7", "hit8", "x9",
+        ] {
+            add(&mut log, text);
+        }
+        let mut app = App::new(Vec::new());
+        app.input = "hit".into();
+        app.context = 1;
+
+        let (list, rows, mut state) = log.get_list(&app, None, 20);
+        assert_eq!(rows, [0, 1, 2, 7, 8, 9]);
+        let area = ratatui::layout::Rect::new(0, 0, 40, 9);
+        let mut buffer = ratatui::buffer::Buffer::empty(area);
+        ratatui::widgets::StatefulWidget::render(list, area, &mut buffer, &mut state);
+        let shown = (1..8)
+            .map(|y| {
+                let line = (1..39)
+                    .map(|x| buffer.get(x, y).symbol())
+                    .collect::<String>();
+                line.split_whitespace()
+                    .last()
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(shown, ["x0", "hit1", "x2", "--", "x7", "hit8", "x9"]);
+    }
+
+    /// The text of each span in 'lines' after the timestamp and level, with highlighted spans in
+    /// brackets
+    fn marked(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                line.spans
+                    .iter()
+                    .skip(if i == 0 { 2 } else { 0 })
+                    .map(|span| match span.style.bg {
+                        Some(ratatui::style::Color::Yellow) => format!("[{}]", span.content),
+                        _ => span.content.to_string(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)] // A single range of highlights is what is wanted
+    fn highlights_carry_on_over_continuation_lines() {
+        let entry = Entry::new(NaiveDateTime::default(), get_level("Info"), "abc\ndef\nghi");
+        assert_eq!(marked(&entry.as_lines(&[1..6])), ["a[bc]", "[de]f", "ghi"]);
+        assert_eq!(
+            marked(&entry.as_lines(&[0..1, 2..3, 9..11])),
+            ["[a]b[c]", "def", "g[hi]"]
+        );
     }
 
-    pub fn as_list_item(&self) -> ListItem {
-        ListItem::new(self.name())
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)] // A single range of highlights is what is wanted
+    fn highlights_skip_the_carriage_return_of_a_line() {
+        // Text read back from a CRLF file keeps the '\r' before each newline
+        let entry = Entry::new(NaiveDateTime::default(), get_level("Info"), "ab\r\ncd");
+        assert_eq!(marked(&entry.as_lines(&[1..5])), ["a[b]", "[c]d"]);
+        assert_eq!(marked(&entry.as_lines(&[2..3])), ["ab", "cd"]);
     }
 }
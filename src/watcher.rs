@@ -0,0 +1,304 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread::{available_parallelism, sleep, spawn},
+    time::Duration,
+};
+
+use chrono::Local;
+
+use crate::{
+    encoding::Encoding,
+    file_filter::FileFilter,
+    format::LogFormat,
+    parser::{
+        find_sources, group_rotations, load_sources, open_log, parse_bytes, rotated_source,
+        LogSource,
+    },
+    types::SharedLog,
+};
+
+/// How often a followed file is checked for new data
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often a watched directory is searched for new log files
+const DIR_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Search 'dir' for log files that werent there before, reading each new one into a log that is
+/// sent through 'sender' and then followed. 'known' holds the sources that are already shown. This
+/// runs until 'sender' is disconnected so it should be run on its own thread
+pub fn watch_dir(
+    dir: &Path,
+    filter: &FileFilter,
+    format: Option<&Arc<dyn LogFormat>>,
+    mut known: HashSet<LogSource>,
+    sender: &Sender<SharedLog>,
+) {
+    // Archives dont change once they have been written, so each one is only searched the first
+    // time it's seen rather than decompressing it again on every pass
+    let mut listed: HashSet<PathBuf> = known
+        .iter()
+        .filter_map(|source| match source {
+            LogSource::Archive { archive, .. } => Some(archive.clone()),
+            LogSource::File(_) => None,
+        })
+        .collect();
+    loop {
+        sleep(DIR_POLL_INTERVAL);
+        let Ok(found) = find_sources(dir, filter, &mut |archive| {
+            listed.insert(archive.to_path_buf())
+        }) else {
+            continue;
+        };
+        let (rotated, new): (Vec<_>, Vec<_>) = found
+            .into_iter()
+            .filter(|source| !known.contains(source))
+            // A log that is rotated while it's being followed has already been read into the
+            // current log, so the rotated copy doesnt need a log of its own
+            .partition(|source| {
+                rotated_source(source).is_some_and(|current| known.contains(&current))
+            });
+        known.extend(rotated);
+        known.extend(new.iter().cloned());
+
+        for sources in group_rotations(new) {
+            let log = open_log(&sources, format);
+            log.lock().unwrap().mark_new();
+            if sender.send(log.clone()).is_err() {
+                return;
+            }
+            let detect = format.is_none();
+            spawn(move || load_and_follow(&log, &sources, detect));
+        }
+    }
+}
+
+/// Load every log in 'logs' in the background, using a few threads so that large folders dont
+/// take too long. Small logs are loaded first so they can be used while the big ones are still
+/// loading. Each log that can change is followed once it has been loaded
+pub fn load_in_background(mut logs: Vec<(SharedLog, Vec<LogSource>)>, detect: bool) {
+    logs.sort_by_cached_key(|(_, sources)| sources.iter().map(LogSource::size_hint).sum::<u64>());
+    let threads = available_parallelism().map_or(1, NonZeroUsize::get);
+    let queue = Arc::new(Mutex::new(logs.into_iter()));
+    for _ in 0..threads {
+        let queue = queue.clone();
+        spawn(move || {
+            loop {
+                // The queue has to be unlocked again before the log is loaded
+                let next = queue.lock().unwrap().next();
+                let Some((log, sources)) = next else {
+                    break;
+                };
+                let position = load_sources(&log, &sources, detect);
+                if let Some(path) = sources.last().and_then(LogSource::followed_path) {
+                    let path = path.to_path_buf();
+                    spawn(move || follow(&path, &log, position));
+                }
+            }
+        });
+    }
+}
+
+/// Load the existing contents of 'sources' into 'log', then follow the current file if it's one
+/// that can change. This never returns for followed files so it should be run on its own thread
+fn load_and_follow(log: &SharedLog, sources: &[LogSource], detect: bool) {
+    let position = load_sources(log, sources, detect);
+    if let Some(path) = sources.last().and_then(LogSource::followed_path) {
+        follow(path, log, position);
+    }
+}
+
+/// Follow the file at 'path' from 'position' onwards, parsing any lines added to it into 'log'.
+/// When the program writing the file restarts and truncates or replaces it, a new session is
+/// started in the log and the new file is read from the beginning. This never returns so it should
+/// be run on its own thread
+pub fn follow(path: &Path, log: &SharedLog, mut position: u64) {
+    // Bytes read from the file that dont make up a complete line yet
+    let mut pending = Vec::new();
+    let mut file: Option<File> = None;
+    // Set after a restart so the encoding is detected again from the new file
+    let mut detect_encoding = false;
+    loop {
+        sleep(POLL_INTERVAL);
+        // The file can briefly disappear while it's being replaced, so errors are just retried
+        let current = match &mut file {
+            Some(file) => file,
+            None => match File::open(path) {
+                Ok(opened) => file.insert(opened),
+                Err(_) => continue,
+            },
+        };
+        let Ok(change) = detect_change(current, path, position) else {
+            continue;
+        };
+        if change == Change::Truncated {
+            finish_file(log, &mut pending, "truncated");
+            position = 0;
+            detect_encoding = true;
+            restart(log, path, "truncated");
+        }
+        let Ok(bytes) = read_from(current, position) else {
+            continue;
+        };
+        position += bytes.len() as u64;
+        pending.extend(bytes);
+
+        if detect_encoding && !pending.is_empty() {
+            log.lock().unwrap().set_encoding(Encoding::detect(&pending));
+            detect_encoding = false;
+        }
+        let encoding = log.lock().unwrap().encoding().unwrap_or(Encoding::Utf8);
+        let complete = encoding.complete_lines_len(&pending);
+        if complete > 0 {
+            let rest = pending.split_off(complete);
+            parse_bytes(&mut log.lock().unwrap(), &pending);
+            pending = rest;
+        }
+
+        // Everything left in the old file has been read by now, so if it has been replaced we can
+        // move over to the new one
+        if change == Change::Replaced {
+            finish_file(log, &mut pending, "replaced");
+            file = None;
+            position = 0;
+            detect_encoding = true;
+            restart(log, path, "replaced");
+        }
+    }
+}
+
+/// What has happened to a followed file since it was last read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Change {
+    /// Nothing, apart from new data being added to the end of it
+    Unchanged,
+    /// The file is shorter than the part of it that has been read, it has been emptied and is
+    /// being written again from the start
+    Truncated,
+    /// The path refers to a different file now, the old one has been moved or deleted and a new
+    /// one created in its place
+    Replaced,
+}
+
+/// Work out what has happened to the open 'file', which has been read up to 'position', since it
+/// was opened from 'path'
+fn detect_change(file: &File, path: &Path, position: u64) -> io::Result<Change> {
+    if file.metadata()?.len() < position {
+        Ok(Change::Truncated)
+    } else if is_replaced(file, path) {
+        Ok(Change::Replaced)
+    } else {
+        Ok(Change::Unchanged)
+    }
+}
+
+/// Parse the last line of a file that wont be written to any more, which was still being written
+/// when the file was truncated or replaced. It's kept as it is, with a diagnostic to say it's
+/// incomplete
+fn finish_file(log: &SharedLog, pending: &mut Vec<u8>, reason: &str) {
+    if pending.is_empty() {
+        return;
+    }
+    let mut log = log.lock().unwrap();
+    parse_bytes(&mut log, pending);
+    let text = log
+        .encoding()
+        .unwrap_or(Encoding::Utf8)
+        .decode(pending)
+        .into_owned();
+    log.add_diagnostic(
+        &text,
+        format!("The file was {reason} before this line was finished"),
+    );
+    drop(log);
+    pending.clear();
+}
+
+/// Close the current session of 'log' and start a new one for the restarted file
+fn restart(log: &SharedLog, path: &Path, reason: &str) {
+    log.lock().unwrap().start_file(
+        &format!(
+            "File {reason} at {}, new run started",
+            Local::now().format("%Y-%m-%d %H:%M:%S")
+        ),
+        Some(path),
+    );
+}
+
+/// Read everything after 'position' in 'file'
+fn read_from(file: &mut File, position: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(position))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Returns true if 'path' now refers to a different file than the open 'file'. While nothing
+/// exists at 'path' the old file is kept, since it will usually be recreated shortly
+#[cfg(unix)]
+fn is_replaced(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(old), Ok(new)) => old.ino() != new.ino() || old.dev() != new.dev(),
+        _ => false,
+    }
+}
+
+/// Without inode numbers we cant tell files apart, so only truncation is detected
+#[cfg(not(unix))]
+fn is_replaced(_file: &File, _path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use super::*;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("valve_log_viewer_follow_{name}.txt"));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn a_file_truncated_in_place_is_detected() {
+        let path = temp_file("truncated", "one\ntwo\n");
+        let file = File::open(&path).unwrap();
+        assert_eq!(detect_change(&file, &path, 8).unwrap(), Change::Unchanged);
+
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"three\n")
+            .unwrap();
+        assert_eq!(detect_change(&file, &path, 8).unwrap(), Change::Unchanged);
+
+        // Rewritten from the start, but already longer than the old start of it
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(detect_change(&file, &path, 14).unwrap(), Change::Truncated);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_file_replaced_by_a_new_one_is_detected() {
+        let path = temp_file("replaced", "one\ntwo\n");
+        let file = File::open(&path).unwrap();
+
+        // While the file is missing it's expected to come back, so the old one is kept
+        let moved = path.with_extension("old");
+        fs::rename(&path, &moved).unwrap();
+        assert_eq!(detect_change(&file, &path, 8).unwrap(), Change::Unchanged);
+
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        assert_eq!(detect_change(&file, &path, 8).unwrap(), Change::Replaced);
+        fs::remove_file(path).unwrap();
+        fs::remove_file(moved).unwrap();
+    }
+}